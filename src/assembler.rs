@@ -0,0 +1,312 @@
+// Assembles Synacor assembly text into a challenge-format binary.
+//
+// The syntax is what interdimensional_physics::decompile writes, so a dump
+// assembles back to the binary it came from:
+//
+//     start:  SET reg0 'H'        ; labels end with a colon
+//             OUT reg0            # either ; or # starts a comment
+//             CALL print
+//             HALT
+//     print:  RET
+//     text:   .data "hello\n" 0 text
+//
+// Operands are decimal or 0x prefixed hex numbers, regN for a register,
+// character literals and label names. .data emits its operands as words, with
+// one word per character of a string literal. The decompiler's own notation
+// is accepted too: DATA? n for a stray word, INVALID n for an out of range
+// operand, "Invalid index" for an operand missing past the end of the binary,
+// and a parenthesised character after an OUT operand.
+use super::synacor_vm::OPCODES;
+use std::collections::HashMap;
+use std::fmt;
+
+const REGISTER_BASE: u32 = 32768;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+	// 1 based source line
+	pub line: usize,
+	pub message: String,
+}
+
+impl fmt::Display for AsmError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: {}", self.line, self.message)
+	}
+}
+
+enum Token {
+	Word(String),
+	Str(Vec<u16>),
+}
+
+enum Operand {
+	Value(u16),
+	Label(String),
+}
+
+fn error(line:usize, message:String) -> AsmError {
+	return AsmError { line, message };
+}
+
+fn unescape(c:char) -> Option<char> {
+	return match c {
+		'n' => Some('\n'),
+		't' => Some('\t'),
+		'0' => Some('\0'),
+		'\\' => Some('\\'),
+		'"' => Some('"'),
+		'\'' => Some('\''),
+		_ => None,
+	};
+}
+
+// split a line into tokens, dropping any comment. Quotes and ; only count at
+// the start of a token and # anywhere outside brackets, so OUT annotations
+// such as (#) or (") stay single tokens.
+fn tokenize(text:&str, line:usize) -> Result<Vec<Token>, AsmError> {
+	let mut tokens = Vec::new();
+	let mut chars = text.chars().peekable();
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+			continue;
+		}
+		if c == '#' || c == ';' {
+			break;
+		}
+		if c == '"' || c == '\'' {
+			chars.next();
+			let mut words = Vec::new();
+			let mut closed = false;
+			while let Some(c2) = chars.next() {
+				if c2 == c {
+					closed = true;
+					break;
+				}
+				let ch = if c2 == '\\' {
+					match chars.next().and_then(unescape) {
+						Some(ch) => ch,
+						None => return Err(error(line, "invalid escape in literal".to_string())),
+					}
+				}
+				else {
+					c2
+				};
+				if ch as u32 > u16::MAX as u32 {
+					return Err(error(line, format!("character {} does not fit in a word", ch)));
+				}
+				words.push(ch as u32 as u16);
+			}
+			if !closed {
+				return Err(error(line, "unterminated literal".to_string()));
+			}
+			if c == '\'' {
+				if words.len() != 1 {
+					return Err(error(line, "character literal must hold one character".to_string()));
+				}
+				tokens.push(Token::Word(words[0].to_string()));
+			}
+			else {
+				tokens.push(Token::Str(words));
+			}
+			continue;
+		}
+		let mut word = String::new();
+		while let Some(&c2) = chars.peek() {
+			// the decompiler leaves no gap before the address comment on a line exactly one tab stop wide
+			if c2.is_whitespace() || (c2 == '#' && !word.starts_with('(')) {
+				break;
+			}
+			word.push(c2);
+			chars.next();
+		}
+		tokens.push(Token::Word(word));
+	}
+	return Ok(tokens);
+}
+
+fn parse_number(text:&str) -> Option<u32> {
+	if let Some(hex) = text.strip_prefix("0x") {
+		return u32::from_str_radix(hex, 16).ok();
+	}
+	return text.parse::<u32>().ok();
+}
+
+// a name that can be defined and used as a label. Register names and the
+// decompiler's INVALID and "Invalid index" markers would be read as operands.
+pub(crate) fn is_label(text:&str) -> bool {
+	let mut chars = text.chars();
+	let well_formed = match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+		_ => false,
+	};
+	let is_register = text.strip_prefix("reg").and_then(parse_number).is_some();
+	return well_formed && !is_register && text != "INVALID" && text != "Invalid";
+}
+
+// a raw word, as written for .data, DATA? and INVALID
+fn parse_word(text:&str, line:usize) -> Result<u16, AsmError> {
+	return match parse_number(text) {
+		Some(val) if val <= u16::MAX as u32 => Ok(val as u16),
+		_ => Err(error(line, format!("invalid word {}", text))),
+	};
+}
+
+// parse up to count operands from tokens starting at pos. Returns the operands
+// and the position after them. Fewer than count are returned only for the
+// decompiler's "Invalid index" marker.
+fn parse_operands(tokens:&[Token], mut pos:usize, count:usize, line:usize) -> Result<(Vec<Operand>, usize), AsmError> {
+	let mut operands = Vec::new();
+	while operands.len() < count {
+		let text = match tokens.get(pos) {
+			Some(Token::Word(text)) => text.as_str(),
+			Some(Token::Str(_)) => return Err(error(line, "string literals are only allowed in .data".to_string())),
+			None => return Err(error(line, format!("expected {} operands, found {}", count, operands.len()))),
+		};
+		pos += 1;
+		if text == "INVALID" {
+			let word = match tokens.get(pos) {
+				Some(Token::Word(word)) => parse_word(word, line)?,
+				_ => return Err(error(line, "expected a word after INVALID".to_string())),
+			};
+			operands.push(Operand::Value(word));
+			pos += 1;
+		}
+		else if text == "Invalid" {
+			// the binary ended before this operand, so the rest are missing
+			// too and each has a marker of its own
+			pos -= 1;
+			for _ in operands.len()..count {
+				match (tokens.get(pos), tokens.get(pos + 1)) {
+					(Some(Token::Word(word)), Some(Token::Word(index))) if word == "Invalid" && index == "index" => pos += 2,
+					_ => return Err(error(line, "expected Invalid index".to_string())),
+				}
+			}
+			return Ok((operands, pos));
+		}
+		else if let Some(reg) = text.strip_prefix("reg").and_then(parse_number) {
+			if REGISTER_BASE + reg > u16::MAX as u32 {
+				return Err(error(line, format!("invalid register {}", text)));
+			}
+			operands.push(Operand::Value((REGISTER_BASE + reg) as u16));
+		}
+		else if let Some(val) = parse_number(text) {
+			if val >= REGISTER_BASE {
+				return Err(error(line, format!("literal {} out of range", text)));
+			}
+			operands.push(Operand::Value(val as u16));
+		}
+		else if is_label(text) {
+			operands.push(Operand::Label(text.to_string()));
+		}
+		else {
+			return Err(error(line, format!("invalid operand {}", text)));
+		}
+	}
+	return Ok((operands, pos));
+}
+
+// assemble source text into words
+pub fn assemble(source:&str) -> Result<Vec<u16>, AsmError> {
+	let mut labels:HashMap<String, u16> = HashMap::new();
+	// words, with label operands to fill in once every label is known
+	let mut words:Vec<u16> = Vec::new();
+	let mut fixups:Vec<(usize, String, usize)> = Vec::new();
+	for (index, text) in source.lines().enumerate() {
+		let line = index + 1;
+		let tokens = tokenize(text, line)?;
+		let mut pos = 0;
+		while let Some(Token::Word(word)) = tokens.get(pos) {
+			let name = match word.strip_suffix(':') {
+				Some(name) if is_label(name) => name,
+				_ => break,
+			};
+			if labels.insert(name.to_string(), words.len() as u16).is_some() {
+				return Err(error(line, format!("duplicate label {}", name)));
+			}
+			pos += 1;
+		}
+		let mnemonic = match tokens.get(pos) {
+			Some(Token::Word(word)) => word.to_uppercase(),
+			Some(Token::Str(_)) => return Err(error(line, "expected an instruction".to_string())),
+			None => continue,
+		};
+		pos += 1;
+		let mut operands = Vec::new();
+		if mnemonic == ".DATA" {
+			while let Some(token) = tokens.get(pos) {
+				match token {
+					Token::Str(chars) => {
+						operands.extend(chars.iter().map(|c| Operand::Value(*c)));
+						pos += 1;
+					},
+					Token::Word(word) => match parse_number(word) {
+						// raw words may be any 16 bit value
+						Some(val) if val <= u16::MAX as u32 => {
+							operands.push(Operand::Value(val as u16));
+							pos += 1;
+						},
+						_ => {
+							let (args, next) = parse_operands(&tokens, pos, 1, line)?;
+							operands.extend(args);
+							pos = next;
+						},
+					},
+				}
+			}
+		}
+		else if mnemonic == "DATA?" {
+			match tokens.get(pos) {
+				Some(Token::Word(word)) => operands.push(Operand::Value(parse_word(word, line)?)),
+				_ => return Err(error(line, "expected a word after DATA?".to_string())),
+			}
+			pos += 1;
+		}
+		else {
+			let opcode = match OPCODES.iter().position(|(name, _)| *name == mnemonic) {
+				Some(opcode) => opcode,
+				None => return Err(error(line, format!("unknown instruction {}", mnemonic))),
+			};
+			operands.push(Operand::Value(opcode as u16));
+			let (args, next) = parse_operands(&tokens, pos, OPCODES[opcode].1, line)?;
+			operands.extend(args);
+			pos = next;
+			// the decompiler shows the character an OUT prints in brackets
+			if opcode == 19 {
+				if let Some(Token::Word(word)) = tokens.get(pos) {
+					if word.starts_with('(') {
+						pos = tokens.len();
+					}
+				}
+			}
+		}
+		if pos < tokens.len() {
+			return Err(error(line, "unexpected text after instruction".to_string()));
+		}
+		for operand in operands {
+			match operand {
+				Operand::Value(val) => words.push(val),
+				Operand::Label(name) => {
+					fixups.push((words.len(), name, line));
+					words.push(0);
+				},
+			}
+		}
+		if words.len() > REGISTER_BASE as usize {
+			return Err(error(line, "program does not fit in memory".to_string()));
+		}
+	}
+	for (addr, name, line) in fixups {
+		match labels.get(&name) {
+			Some(target) => words[addr] = *target,
+			None => return Err(error(line, format!("undefined label {}", name))),
+		}
+	}
+	return Ok(words);
+}
+
+// little endian bytes, as read by the vm loader
+pub fn to_bytes(words:&[u16]) -> Vec<u8> {
+	return words.iter().flat_map(|word| word.to_le_bytes()).collect();
+}
@@ -0,0 +1,50 @@
+use super::synacor_vm::SynacorVM;
+use std::time::Instant;
+
+pub struct BenchResult {
+	pub instructions: u64,
+	pub seconds: f64,
+}
+
+impl BenchResult {
+	pub fn per_second(&self) -> f64 {
+		if self.seconds <= 0.0 {
+			return 0.0;
+		}
+		return self.instructions as f64 / self.seconds;
+	}
+}
+
+// time program from the start until it halts, waits for input or has executed
+// max_instructions. Short runs are repeated on a fresh vm until max_instructions
+// have been executed in total, so that programs which stop early still give a
+// stable rate.
+pub fn run(program:&[u16], max_instructions:u64, decode_cache:bool) -> BenchResult {
+	let mut result = BenchResult { instructions: 0, seconds: 0.0 };
+	while result.instructions < max_instructions {
+		let mut vm = SynacorVM::new(false);
+		vm.set_decode_cache(decode_cache);
+		let _ = vm.load_mem(program.to_vec());
+		let start = Instant::now();
+		vm.execute_for(max_instructions - result.instructions);
+		result.seconds += start.elapsed().as_secs_f64();
+		if vm.instruction_count() == 0 {
+			break;
+		}
+		result.instructions += vm.instruction_count();
+	}
+	return result;
+}
+
+// run the benchmark with the decode cache off and on, and format both rates
+pub fn compare(program:&[u16], max_instructions:u64) -> String {
+	let before = run(program, max_instructions, false);
+	let after = run(program, max_instructions, true);
+	let mut out = String::new();
+	out.push_str(&format!("{:<16} {:>12} instructions {:>8.3}s {:>8.1}M/s\n", "no decode cache", before.instructions, before.seconds, before.per_second() / 1e6));
+	out.push_str(&format!("{:<16} {:>12} instructions {:>8.3}s {:>8.1}M/s\n", "decode cache", after.instructions, after.seconds, after.per_second() / 1e6));
+	if before.per_second() > 0.0 {
+		out.push_str(&format!("speedup {:.2}x\n", after.per_second() / before.per_second()));
+	}
+	return out;
+}
@@ -0,0 +1,256 @@
+// Basic blocks and control-flow graphs over the code the disassembler found,
+// written as Graphviz DOT for rendering with dot -Tsvg.
+//
+// A block is a run of instructions entered only at the top and left only at
+// the bottom. CALL does not end a block, since control comes back to the next
+// instruction. A function's graph holds the blocks reachable from its entry
+// without entering another function; a jump into another function is drawn
+// as a dashed edge to a node for that function.
+use super::disassembler::{Disassembly, instruction_line, instruction_len, literal_target, successors};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+	pub start: u16,
+	// address of each instruction, in order
+	pub instructions: Vec<u16>,
+	// first instructions of the blocks that may run next
+	pub successors: Vec<u16>,
+}
+
+impl BasicBlock {
+	pub fn last(&self) -> u16 {
+		return *self.instructions.last().unwrap();
+	}
+}
+
+// HALT, RET, JMP, JT and JF end a block
+fn ends_block(opcode:u16) -> bool {
+	return matches!(opcode, 0 | 18 | 6 | 7 | 8);
+}
+
+// instructions that may run after the one at addr within its function
+fn block_successors(program:&[u16], addr:usize) -> Vec<usize> {
+	if program[addr] == 17 {
+		return vec![addr + instruction_len(program, addr)];
+	}
+	return successors(program, addr);
+}
+
+// split the code into basic blocks, keyed by their first instruction
+pub fn basic_blocks(program:&[u16], disassembly:&Disassembly) -> BTreeMap<u16, BasicBlock> {
+	let map = &disassembly.map;
+	let instructions = map.instructions();
+	// a block starts at a function entry, a jump target, or an instruction
+	// not reached by falling through from the one before it
+	let mut leaders:BTreeSet<u16> = disassembly.functions.iter().map(|function| function.entry).collect();
+	let mut fallthrough = None;
+	for addr in instructions.iter() {
+		let index = *addr as usize;
+		if fallthrough != Some(index) {
+			leaders.insert(*addr);
+		}
+		// JMP, JT and JF
+		if (6..=8).contains(&program[index]) {
+			for target in successors(program, index) {
+				if map.is_instruction(target as u16) {
+					leaders.insert(target as u16);
+				}
+			}
+		}
+		fallthrough = match ends_block(program[index]) {
+			true => None,
+			false => Some(index + instruction_len(program, index)),
+		};
+	}
+	let mut blocks = BTreeMap::new();
+	let mut current:Option<BasicBlock> = None;
+	for addr in instructions {
+		let index = addr as usize;
+		if leaders.contains(&addr) {
+			if let Some(block) = current.take() {
+				blocks.insert(block.start, block);
+			}
+		}
+		let block = current.get_or_insert_with(|| BasicBlock { start: addr, instructions: Vec::new(), successors: Vec::new() });
+		block.instructions.push(addr);
+		let next = block_successors(program, index);
+		let ends = ends_block(program[index]) || next.iter().any(|next| leaders.contains(&(*next as u16)));
+		if ends {
+			block.successors = next.into_iter().filter(|next| map.is_instruction(*next as u16)).map(|next| next as u16).collect();
+			blocks.insert(block.start, current.take().unwrap());
+		}
+	}
+	if let Some(block) = current {
+		blocks.insert(block.start, block);
+	}
+	return blocks;
+}
+
+// starts of the blocks in the function at entry, in address order
+pub fn function_blocks(blocks:&BTreeMap<u16, BasicBlock>, disassembly:&Disassembly, entry:u16) -> Vec<u16> {
+	let entries:BTreeSet<u16> = disassembly.functions.iter().map(|function| function.entry).collect();
+	let mut seen = BTreeSet::new();
+	let mut pending = vec![entry];
+	while let Some(start) = pending.pop() {
+		if (start != entry && entries.contains(&start)) || !seen.insert(start) {
+			continue;
+		}
+		if let Some(block) = blocks.get(&start) {
+			pending.extend(block.successors.iter().cloned());
+		}
+	}
+	return seen.into_iter().filter(|start| blocks.contains_key(start)).collect();
+}
+
+fn label_of(disassembly:&Disassembly, addr:u16) -> String {
+	return match disassembly.labels.get(&addr) {
+		Some(label) => label.clone(),
+		None => addr.to_string(),
+	};
+}
+
+// text for a double quoted DOT string. Tabs are expanded so the address
+// comments stay lined up.
+fn escape(text:&str) -> String {
+	let mut out = String::new();
+	let mut column = 0;
+	for c in text.chars() {
+		match c {
+			'\t' => {
+				out.push(' ');
+				column += 1;
+				while column % 4 != 0 {
+					out.push(' ');
+					column += 1;
+				}
+				continue;
+			},
+			'\\' | '"' => out.push('\\'),
+			_ => (),
+		}
+		out.push(c);
+		column += 1;
+	}
+	return out;
+}
+
+// the label of a block node: its disassembly, one left aligned line per instruction
+fn block_label(program:&[u16], disassembly:&Disassembly, callback_loads:&BTreeSet<u16>, block:&BasicBlock) -> String {
+	let mut label = String::new();
+	if let Some(name) = disassembly.labels.get(&block.start) {
+		label.push_str(&escape(&format!("{}:", name)));
+		label.push_str("\\l");
+	}
+	for addr in block.instructions.iter() {
+		let (line, _) = instruction_line(program, *addr as usize, &disassembly.labels, &disassembly.comments, callback_loads.contains(addr));
+		label.push_str(&escape(&line));
+		label.push_str("\\l");
+	}
+	return label;
+}
+
+// the label on an edge out of a conditional jump
+fn branch_label(program:&[u16], block:&BasicBlock, target:u16) -> Option<&'static str> {
+	let last = block.last() as usize;
+	let taken = literal_target(program, last, 2) == Some(target as usize);
+	return match (program[last], taken) {
+		(7, true) | (8, false) => Some("true"),
+		(7, false) | (8, true) => Some("false"),
+		_ => None,
+	};
+}
+
+// Graphviz DOT for the control flow of the function at entry
+pub fn function_dot(program:&[u16], disassembly:&Disassembly, blocks:&BTreeMap<u16, BasicBlock>, entry:u16) -> String {
+	let callback_loads = disassembly.callback_loads();
+	let starts = function_blocks(blocks, disassembly, entry);
+	let mut out = format!("digraph \"{}\" {{\n", escape(&label_of(disassembly, entry)));
+	out.push_str("\tnode [shape=box, fontname=\"monospace\"];\n");
+	let mut external = BTreeSet::new();
+	for start in starts.iter() {
+		let block = &blocks[start];
+		out.push_str(&format!("\tb{} [label=\"{}\"];\n", start, block_label(program, disassembly, &callback_loads, block)));
+		for target in block.successors.iter() {
+			let mut attrs = Vec::new();
+			if let Some(label) = branch_label(program, block, *target) {
+				attrs.push(format!("label=\"{}\"", label));
+			}
+			if !starts.contains(target) {
+				external.insert(*target);
+				attrs.push("style=dashed".to_string());
+			}
+			match attrs.len() {
+				0 => out.push_str(&format!("\tb{} -> b{};\n", start, target)),
+				_ => out.push_str(&format!("\tb{} -> b{} [{}];\n", start, target, attrs.join(", "))),
+			}
+		}
+	}
+	for target in external {
+		out.push_str(&format!("\tb{} [label=\"{}\", shape=ellipse];\n", target, escape(&label_of(disassembly, target))));
+	}
+	out.push_str("}\n");
+	return out;
+}
+
+// Graphviz DOT with one node per function and an edge for each function it
+// calls, labelled with the number of call sites when there is more than one.
+// Callbacks a function loads are dotted edges, and calls through a register
+// go to a single indirect node.
+pub fn call_graph_dot(program:&[u16], disassembly:&Disassembly, blocks:&BTreeMap<u16, BasicBlock>) -> String {
+	let entries:BTreeSet<u16> = disassembly.functions.iter().map(|function| function.entry).collect();
+	let callback_loads = disassembly.callback_loads();
+	let mut out = String::from("digraph calls {\n");
+	out.push_str("\tnode [shape=box];\n");
+	for function in disassembly.functions.iter() {
+		out.push_str(&format!("\tfn_{} [label=\"{}\\n{}-{}\"];\n", function.entry, escape(&label_of(disassembly, function.entry)), function.entry, function.end));
+	}
+	let mut indirect = false;
+	for function in disassembly.functions.iter() {
+		// callee -> call sites, and callbacks loaded
+		let mut calls:BTreeMap<u16, usize> = BTreeMap::new();
+		let mut callbacks = BTreeSet::new();
+		let mut calls_indirect = false;
+		for start in function_blocks(blocks, disassembly, function.entry) {
+			for addr in blocks[&start].instructions.iter() {
+				let index = *addr as usize;
+				let (operand, is_call) = match program[index] {
+					17 => (1, true),
+					1 if callback_loads.contains(addr) => (2, false),
+					2 if callback_loads.contains(addr) => (1, false),
+					_ => continue,
+				};
+				match literal_target(program, index, operand) {
+					Some(target) if entries.contains(&(target as u16)) => {
+						if is_call {
+							*calls.entry(target as u16).or_insert(0) += 1;
+						}
+						else {
+							callbacks.insert(target as u16);
+						}
+					},
+					Some(_) => (),
+					None => calls_indirect |= is_call && program[index + 1] > 32767,
+				}
+			}
+		}
+		for (callee, sites) in calls {
+			match sites {
+				1 => out.push_str(&format!("\tfn_{} -> fn_{};\n", function.entry, callee)),
+				_ => out.push_str(&format!("\tfn_{} -> fn_{} [label=\"{}\"];\n", function.entry, callee, sites)),
+			}
+		}
+		for callback in callbacks {
+			out.push_str(&format!("\tfn_{} -> fn_{} [style=dotted];\n", function.entry, callback));
+		}
+		if calls_indirect {
+			out.push_str(&format!("\tfn_{} -> indirect [style=dashed];\n", function.entry));
+			indirect = true;
+		}
+	}
+	if indirect {
+		out.push_str("\tindirect [label=\"call through a register\", shape=ellipse];\n");
+	}
+	out.push_str("}\n");
+	return out;
+}
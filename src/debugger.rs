@@ -0,0 +1,351 @@
+use super::synacor_vm::{SynacorVM, StopReason};
+use super::disassembler::instruction_line;
+use super::symbols::Symbols;
+use std::io;
+use std::io::Write;
+
+// instructions the debugger can rewind
+const JOURNAL_CAPACITY: usize = 200000;
+
+const HELP: &str = "Commands:
+  step [n]                  execute n instructions (default 1)
+  continue                  run until halt, input or a breakpoint
+  input <text>              send a line of game input and continue
+  back [n]                  undo n instructions (default 1)
+  backwrite <addr>          undo back to before the last write to addr
+  undo                      undo back to before the last line of game input
+  break <addr>              set a breakpoint
+  delete <addr>             remove a breakpoint
+  watch read|write <addr>   stop after an instruction reads or writes memory at addr
+  watch reg <n>             stop after an instruction writes register n
+  unwatch read|write <addr> remove a memory watchpoint
+  unwatch reg <n>           remove a register watchpoint
+  breakpoints               list breakpoints and watchpoints
+  regs                      show registers and the program counter
+  setreg <n> <val>          set register n
+  pc <addr>                 set the program counter
+  mem <addr> [len]          show memory
+  poke <addr> <val>         write memory
+  stack                     show the stack, top first
+  bt                        show the guest call chain, innermost first
+  calls [n]                 show the n most frequent calls (default 10) and deepest recursion
+  disasm [addr] [count]     disassemble from addr (default pc)
+  mark                      remember the current state for diff
+  diff                      show memory, registers, stack and pc changed since mark
+  quit                      leave the debugger
+Addresses and values may be decimal or 0x prefixed hex.";
+
+// numbers are decimal unless 0x prefixed
+fn parse_num(s:&str) -> Option<u16> {
+	if s.starts_with("0x") || s.starts_with("0X") {
+		return u16::from_str_radix(&s[2..], 16).ok();
+	}
+	return s.parse::<u16>().ok();
+}
+fn parse_arg(args:&[&str], index:usize, name:&str) -> Option<u16> {
+	if index >= args.len() {
+		println!("Missing {}", name);
+		return None;
+	}
+	let val = parse_num(args[index]);
+	if val.is_none() {
+		println!("Invalid {} {}", name, args[index]);
+	}
+	return val;
+}
+fn print_instruction(vm:&SynacorVM, symbols:&Symbols, addr:u16, count:usize) {
+	let mem = vm.dump_mem();
+	let mut index = addr as usize;
+	for _ in 0..count {
+		if index >= mem.len() {
+			break;
+		}
+		if let Some(name) = symbols.name(index as u16) {
+			println!("{}:", name);
+		}
+		let (line, len) = instruction_line(&mem, index, symbols.names(), symbols.comments(), false);
+		println!("{}", line);
+		index += len;
+	}
+}
+fn print_regs(vm:&mut SynacorVM) {
+	let mut line = String::new();
+	for r in 0..8 {
+		line.push_str(&format!("r{}={:<6}", r, vm.get_register(r)));
+	}
+	println!("{}", line);
+	println!("pc={}", vm.get_mem_ptr());
+}
+fn print_mem(vm:&mut SynacorVM, addr:u16, len:u16) {
+	let per_line = 8;
+	let mut line = String::new();
+	for i in 0..len {
+		let a = addr as u32 + i as u32;
+		if a > u16::MAX as u32 {
+			break;
+		}
+		let val = match vm.get_mem(a as u16) {
+			Some(v) => v,
+			None => break,
+		};
+		if i % per_line == 0 {
+			if !line.is_empty() {
+				println!("{}", line);
+			}
+			line = format!("{:>5}:", a);
+		}
+		line.push_str(&format!(" {:>5}", val));
+	}
+	if !line.is_empty() {
+		println!("{}", line);
+	}
+}
+fn print_stack(vm:&SynacorVM) {
+	let stack = vm.get_stack();
+	if stack.is_empty() {
+		println!("Stack is empty");
+		return;
+	}
+	for (depth, val) in stack.iter().rev().enumerate() {
+		println!("{:>4}: {}", depth, val);
+	}
+}
+fn print_breakpoints(vm:&SynacorVM) {
+	let bp = vm.breakpoints();
+	println!("Breakpoints: {:?}", bp.pc_addrs());
+	println!("Read watchpoints: {:?}", bp.read_addrs());
+	println!("Write watchpoints: {:?}", bp.write_addrs());
+	println!("Register watchpoints: {:?}", bp.registers());
+	println!("Hooks: {:?}", vm.hook_addrs());
+}
+fn print_backtrace(vm:&SynacorVM, symbols:&Symbols) {
+	let frames = vm.backtrace();
+	if frames.is_empty() {
+		println!("No calls in progress");
+		return;
+	}
+	for (depth, frame) in frames.iter().enumerate() {
+		println!("{:>4}: fn {} called from {}, returns to {}", depth, symbols.describe(frame.entry), symbols.describe(frame.call_site), symbols.describe(frame.return_addr));
+	}
+}
+fn print_calls(vm:&SynacorVM, symbols:&Symbols, count:usize) {
+	let graph = vm.call_graph();
+	println!("Deepest call stack: {}", graph.max_call_depth());
+	println!("Most frequent calls:");
+	for (caller, callee, calls) in graph.edges().into_iter().take(count) {
+		let from = match caller {
+			Some(caller) => format!("fn {}", symbols.describe(caller)),
+			None => "top level".to_string(),
+		};
+		println!("{:>10}  {} -> fn {}", calls, from, symbols.describe(callee));
+	}
+	println!("Deepest recursion:");
+	for (entry, depth) in graph.max_recursion_depths().into_iter().take(count) {
+		println!("{:>10}  fn {}", depth, symbols.describe(entry));
+	}
+}
+// game output is buffered while the debugger is in control
+fn print_output(vm:&mut SynacorVM) {
+	let output = vm.output_line(true);
+	print!("{}", output);
+	if !output.is_empty() && !output.ends_with('\n') {
+		println!();
+	}
+}
+fn report_stop(vm:&mut SynacorVM, symbols:&Symbols, stop:&StopReason) {
+	print_output(vm);
+	match stop {
+		StopReason::Halted(reason) => println!("{}", reason),
+		StopReason::AwaitingInput => println!("Waiting for game input (use: input <text>)"),
+		StopReason::Breakpoint { addr } => println!("Breakpoint at {}", symbols.describe(*addr)),
+		StopReason::MemoryRead { pc, addr, val } => println!("Instruction at {} read {} from address {}", pc, val, symbols.describe(*addr)),
+		StopReason::MemoryWrite { pc, addr, old, new } => println!("Instruction at {} wrote address {}: {} -> {}", pc, symbols.describe(*addr), old, new),
+		StopReason::RegisterWrite { pc, reg, old, new } => println!("Instruction at {} wrote r{}: {} -> {}", pc, reg, old, new),
+		StopReason::BudgetExhausted => println!("Instruction budget exhausted"),
+		StopReason::Condition => println!("Stop condition reached"),
+	}
+}
+fn step(vm:&mut SynacorVM, symbols:&Symbols, count:u16) {
+	if vm.is_halted() || vm.is_awaiting_input() {
+		// nothing to step, execute() just reports why
+		let stop = vm.execute();
+		report_stop(vm, symbols, &stop);
+		return;
+	}
+	for _ in 0..count {
+		if let Some(stop) = vm.execute_once() {
+			report_stop(vm, symbols, &stop);
+			break;
+		}
+	}
+	print_output(vm);
+	let pc = vm.get_mem_ptr();
+	print_instruction(vm, symbols, pc, 1);
+}
+fn watch(vm:&mut SynacorVM, args:&[&str], add:bool) {
+	if args.len() < 3 {
+		println!("Usage: {} read|write <addr> or {} reg <n>", args[0], args[0]);
+		return;
+	}
+	let target = match parse_arg(args, 2, "address") {
+		Some(t) => t,
+		None => return,
+	};
+	if args[1] == "reg" && target >= 8 {
+		println!("Invalid register {}", target);
+		return;
+	}
+	let bp = vm.breakpoints_mut();
+	match (args[1], add) {
+		("read", true) => bp.add_read(target),
+		("read", false) => { bp.remove_read(target); },
+		("write", true) => bp.add_write(target),
+		("write", false) => { bp.remove_write(target); },
+		("reg", true) => { bp.add_register(target as usize); },
+		("reg", false) => { bp.remove_register(target as usize); },
+		_ => println!("Unknown watchpoint type {}", args[1]),
+	}
+}
+
+// run the vm under a command prompt. The vm is put in non-interactive mode
+// so game input and output stay separate from debugger commands.
+pub fn run(vm:&mut SynacorVM, symbols:&Symbols) {
+	vm.set_interactive(false);
+	vm.enable_journal(JOURNAL_CAPACITY);
+	println!("Synacor VM debugger. Type help for a list of commands.");
+	let pc = vm.get_mem_ptr();
+	print_instruction(vm, symbols, pc, 1);
+	let stdin = io::stdin();
+	let mut mark:Option<SynacorVM> = None;
+	loop {
+		print!("dbg> ");
+		io::stdout().flush().unwrap();
+		let mut line = String::new();
+		if stdin.read_line(&mut line).unwrap_or(0) == 0 {
+			return;
+		}
+		let args:Vec<&str> = line.split_whitespace().collect();
+		if args.is_empty() {
+			continue;
+		}
+		match args[0] {
+			"help" | "h" | "?" => println!("{}", HELP),
+			"quit" | "q" => return,
+			"step" | "s" => {
+				let count = if args.len() > 1 { parse_arg(&args, 1, "count") } else { Some(1) };
+				if let Some(count) = count {
+					step(vm, symbols, count);
+				}
+			},
+			"continue" | "c" => {
+				let stop = vm.execute();
+				report_stop(vm, symbols, &stop);
+			},
+			"input" | "i" => {
+				if !vm.is_awaiting_input() {
+					println!("The game is not waiting for input");
+					continue;
+				}
+				let text = line.trim_start()[args[0].len()..].trim();
+				vm.set_input_line(text);
+				let stop = vm.execute();
+				report_stop(vm, symbols, &stop);
+			},
+			"back" => {
+				let count = if args.len() > 1 { parse_arg(&args, 1, "count") } else { Some(1) };
+				if let Some(count) = count {
+					for i in 0..count {
+						if !vm.step_back() {
+							println!("Undo journal exhausted after {} instructions", i);
+							break;
+						}
+					}
+					let pc = vm.get_mem_ptr();
+					print_instruction(vm, symbols, pc, 1);
+				}
+			},
+			"backwrite" => {
+				if let Some(addr) = parse_arg(&args, 1, "address") {
+					if !vm.run_back_to_write(addr) {
+						println!("No write to {} in the undo journal", addr);
+					}
+					let pc = vm.get_mem_ptr();
+					print_instruction(vm, symbols, pc, 1);
+				}
+			},
+			"undo" => {
+				if !vm.undo_command() {
+					println!("No game input in the undo journal");
+				}
+				let pc = vm.get_mem_ptr();
+				print_instruction(vm, symbols, pc, 1);
+			},
+			"break" | "b" => {
+				if let Some(addr) = parse_arg(&args, 1, "address") {
+					vm.breakpoints_mut().add_pc(addr);
+				}
+			},
+			"delete" | "d" => {
+				if let Some(addr) = parse_arg(&args, 1, "address") {
+					if !vm.breakpoints_mut().remove_pc(addr) {
+						println!("No breakpoint at {}", addr);
+					}
+				}
+			},
+			"watch" => watch(vm, &args, true),
+			"unwatch" => watch(vm, &args, false),
+			"breakpoints" => print_breakpoints(vm),
+			"regs" | "r" => print_regs(vm),
+			"setreg" => {
+				if let (Some(reg), Some(val)) = (parse_arg(&args, 1, "register"), parse_arg(&args, 2, "value")) {
+					if reg >= 8 {
+						println!("Invalid register {}", reg);
+						continue;
+					}
+					vm.set_register(reg as usize, val);
+				}
+			},
+			"pc" => {
+				if let Some(addr) = parse_arg(&args, 1, "address") {
+					vm.set_mem_ptr(addr);
+				}
+			},
+			"mem" | "m" => {
+				let len = if args.len() > 2 { parse_arg(&args, 2, "length") } else { Some(8) };
+				if let (Some(addr), Some(len)) = (parse_arg(&args, 1, "address"), len) {
+					print_mem(vm, addr, len);
+				}
+			},
+			"poke" => {
+				if let (Some(addr), Some(val)) = (parse_arg(&args, 1, "address"), parse_arg(&args, 2, "value")) {
+					if !vm.set_mem(addr, val) {
+						println!("Address {} out of range", addr);
+					}
+				}
+			},
+			"stack" => print_stack(vm),
+			"bt" | "backtrace" => print_backtrace(vm, symbols),
+			"calls" => {
+				let count = if args.len() > 1 { parse_arg(&args, 1, "count") } else { Some(10) };
+				if let Some(count) = count {
+					print_calls(vm, symbols, count as usize);
+				}
+			},
+			"disasm" | "x" => {
+				let addr = if args.len() > 1 { parse_arg(&args, 1, "address") } else { Some(vm.get_mem_ptr()) };
+				let count = if args.len() > 2 { parse_arg(&args, 2, "count") } else { Some(10) };
+				if let (Some(addr), Some(count)) = (addr, count) {
+					print_instruction(vm, symbols, addr, count as usize);
+				}
+			},
+			"mark" => mark = Some(vm.clone()),
+			"diff" => {
+				match mark.as_ref() {
+					Some(mark) => print!("{}", mark.diff(vm)),
+					None => println!("No mark set. Use mark first."),
+				}
+			},
+			_ => println!("Unknown command {}. Type help for a list of commands.", args[0]),
+		}
+	}
+}
@@ -0,0 +1,382 @@
+// Recursive-descent disassembly. Code is found by following control flow from
+// the entry point rather than decoding every word in turn, so data and the
+// encrypted strings between functions are listed as data instead of as bogus
+// instructions that throw the listing out of step.
+//
+// Calls and jumps through registers cannot be followed. To find the routines
+// the challenge binary passes around as callbacks, literal addresses loaded
+// with SET or PUSH are tried as entry points as well, and kept only if
+// everything reachable from them decodes cleanly without running into known
+// code out of step.
+//
+// Functions are the entry points, CALL targets and callbacks found this way.
+// A function's body is the code reachable from its entry without following
+// calls, and jump targets inside code get loc_ labels. The listing names them
+// fn_<addr> and loc_<addr> and uses the names as operands. Names and comments
+// from a symbol file take precedence.
+use super::synacor_vm::OPCODES;
+use super::interdimensional_physics::{decompile_instruction, decompiler_val, append_with_tabs};
+use super::symbols::Symbols;
+use std::collections::{BTreeSet, HashMap};
+
+// words per line in a data block
+const DATA_LINE_WORDS: usize = 8;
+
+// which words of a program are code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeMap {
+	// the first word of an instruction
+	starts: Vec<bool>,
+	// any word of an instruction
+	code: Vec<bool>,
+	// the entry points given and the callbacks found
+	roots: BTreeSet<usize>,
+}
+
+impl CodeMap {
+	fn new(len:usize) -> CodeMap {
+		return CodeMap { starts: vec![false; len], code: vec![false; len], roots: BTreeSet::new() };
+	}
+	pub fn is_instruction(&self, addr:u16) -> bool {
+		return *self.starts.get(addr as usize).unwrap_or(&false);
+	}
+	pub fn is_code(&self, addr:u16) -> bool {
+		return *self.code.get(addr as usize).unwrap_or(&false);
+	}
+	// addresses of every instruction found, in order
+	pub fn instructions(&self) -> Vec<u16> {
+		return (0..self.starts.len()).filter(|addr| self.starts[*addr]).map(|addr| addr as u16).collect();
+	}
+	fn add(&mut self, program:&[u16], addr:usize) {
+		self.starts[addr] = true;
+		for word in addr..addr + instruction_len(program, addr) {
+			self.code[word] = true;
+		}
+	}
+}
+
+// words taken by the instruction at addr, including operands, or 0 if the
+// opcode is undefined or its operands run past the end of the program
+pub(crate) fn instruction_len(program:&[u16], addr:usize) -> usize {
+	return match OPCODES.get(program[addr] as usize) {
+		Some((_, operands)) if addr + operands < program.len() => operands + 1,
+		_ => 0,
+	};
+}
+
+// the literal operand n of the instruction at addr, if it is an address in the program
+pub(crate) fn literal_target(program:&[u16], addr:usize, n:usize) -> Option<usize> {
+	let val = program[addr + n] as usize;
+	if val < program.len() {
+		return Some(val);
+	}
+	return None;
+}
+
+// instructions that may run after the one at addr
+pub(crate) fn successors(program:&[u16], addr:usize) -> Vec<usize> {
+	let next = addr + instruction_len(program, addr);
+	return match program[addr] {
+		// HALT and RET
+		0 | 18 => Vec::new(),
+		// JMP
+		6 => literal_target(program, addr, 1).into_iter().collect(),
+		// JT, JF
+		7 | 8 => literal_target(program, addr, 2).into_iter().chain(Some(next)).collect(),
+		// CALL
+		17 => literal_target(program, addr, 1).into_iter().chain(Some(next)).collect(),
+		_ => vec![next],
+	};
+}
+
+// instructions reachable from entry that are not in map yet. Paths that reach
+// an undefined opcode, the end of the program or the middle of a known
+// instruction are dropped, or in strict mode reject the whole entry point.
+fn trace(program:&[u16], entry:usize, map:&CodeMap, strict:bool) -> Option<Vec<usize>> {
+	let mut found = CodeMap::new(program.len());
+	let mut order = Vec::new();
+	let mut pending = vec![entry];
+	while let Some(addr) = pending.pop() {
+		if addr >= program.len() || map.starts[addr] || found.starts[addr] {
+			continue;
+		}
+		let len = instruction_len(program, addr);
+		let overlaps = (addr..addr + len.max(1)).any(|word| map.code.get(word) == Some(&true) || found.code.get(word) == Some(&true));
+		if len == 0 || overlaps {
+			if strict {
+				return None;
+			}
+			continue;
+		}
+		found.add(program, addr);
+		order.push(addr);
+		pending.extend(successors(program, addr));
+	}
+	return Some(order);
+}
+
+// literal addresses loaded by SET or PUSH in the code found so far
+fn code_pointers(program:&[u16], map:&CodeMap) -> Vec<usize> {
+	let mut pointers = Vec::new();
+	for addr in map.instructions() {
+		let addr = addr as usize;
+		let pointer = match program[addr] {
+			1 => literal_target(program, addr, 2),
+			2 => literal_target(program, addr, 1),
+			_ => None,
+		};
+		if let Some(pointer) = pointer {
+			if !map.code[pointer] {
+				pointers.push(pointer);
+			}
+		}
+	}
+	pointers.sort();
+	pointers.dedup();
+	return pointers;
+}
+
+// find the code reachable from the entry points
+pub fn find_code(program:&[u16], entries:&[u16]) -> CodeMap {
+	let mut map = CodeMap::new(program.len());
+	for entry in entries {
+		for addr in trace(program, *entry as usize, &map, false).unwrap_or_default() {
+			map.add(program, addr);
+		}
+		if map.is_instruction(*entry) {
+			map.roots.insert(*entry as usize);
+		}
+	}
+	loop {
+		let mut changed = false;
+		for pointer in code_pointers(program, &map) {
+			if let Some(found) = trace(program, pointer, &map, true) {
+				map.roots.insert(pointer);
+				for addr in found {
+					map.add(program, addr);
+					changed = true;
+				}
+			}
+		}
+		if !changed {
+			return map;
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function {
+	pub entry: u16,
+	// last word of the highest instruction in the body
+	pub end: u16,
+	// addresses of the CALL instructions that call it
+	pub callers: Vec<u16>,
+	// addresses of the SET and PUSH instructions that load it as a callback
+	pub references: Vec<u16>,
+}
+
+// what the disassembler found in a program
+pub struct Disassembly {
+	pub map: CodeMap,
+	// in address order
+	pub functions: Vec<Function>,
+	// symbolic names for function entries and jump targets
+	pub labels: HashMap<u16, String>,
+	// notes shown after the instruction or data at an address
+	pub comments: HashMap<u16, String>,
+}
+
+impl Disassembly {
+	// use the names and comments in symbols in place of the generated labels.
+	// Names of addresses inside an instruction's operands, or past the end of
+	// the program, cannot be given a label line and are left out.
+	pub fn apply_symbols(&mut self, symbols:&Symbols) {
+		// a generated label that clashes with a user name goes back to being a number
+		self.labels.retain(|_, label| !symbols.names().values().any(|name| name == label));
+		for (addr, name) in symbols.names() {
+			if (*addr as usize) < self.map.starts.len() && (self.map.is_instruction(*addr) || !self.map.is_code(*addr)) {
+				self.labels.insert(*addr, name.clone());
+			}
+		}
+		for (addr, comment) in symbols.comments() {
+			self.comments.insert(*addr, comment.clone());
+		}
+	}
+	// addresses of the SET and PUSH instructions that load a callback
+	pub(crate) fn callback_loads(&self) -> BTreeSet<u16> {
+		return self.functions.iter().flat_map(|function| function.references.iter().cloned()).collect();
+	}
+}
+
+// the last word of the code reachable from entry without following calls or
+// entering another function
+fn function_end(program:&[u16], entry:usize, entries:&BTreeSet<usize>) -> u16 {
+	let mut seen = BTreeSet::new();
+	let mut pending = vec![entry];
+	let mut end = entry;
+	while let Some(addr) = pending.pop() {
+		if addr >= program.len() || (addr != entry && entries.contains(&addr)) || !seen.insert(addr) {
+			continue;
+		}
+		let len = instruction_len(program, addr);
+		if len == 0 {
+			continue;
+		}
+		end = end.max(addr + len - 1);
+		let mut next = successors(program, addr);
+		if program[addr] == 17 {
+			// only the return from a call stays in the function
+			next = vec![addr + len];
+		}
+		pending.extend(next);
+	}
+	return end as u16;
+}
+
+// find the code, functions and jump targets reachable from the entry points
+pub fn analyze(program:&[u16], entries:&[u16]) -> Disassembly {
+	let map = find_code(program, entries);
+	let mut callers:HashMap<usize, Vec<u16>> = HashMap::new();
+	let mut references:HashMap<usize, Vec<u16>> = HashMap::new();
+	let mut jump_targets = BTreeSet::new();
+	for addr in map.instructions() {
+		let index = addr as usize;
+		let target = match program[index] {
+			1 | 7 | 8 => literal_target(program, index, 2),
+			2 | 6 | 17 => literal_target(program, index, 1),
+			_ => None,
+		};
+		let target = match target {
+			Some(target) if map.starts[target] => target,
+			_ => continue,
+		};
+		match program[index] {
+			17 => callers.entry(target).or_default().push(addr),
+			1 | 2 => references.entry(target).or_default().push(addr),
+			_ => { jump_targets.insert(target); },
+		}
+	}
+	// a literal that happens to be the address of an instruction does not make
+	// a function, only one that led find_code() to new code
+	let mut entry_set:BTreeSet<usize> = map.roots.clone();
+	entry_set.extend(callers.keys());
+	let mut functions = Vec::new();
+	let mut labels = HashMap::new();
+	for entry in entry_set.iter() {
+		functions.push(Function {
+			entry: *entry as u16,
+			end: function_end(program, *entry, &entry_set),
+			callers: callers.remove(entry).unwrap_or_default(),
+			references: references.remove(entry).unwrap_or_default(),
+		});
+		labels.insert(*entry as u16, format!("fn_{}", entry));
+	}
+	for target in jump_targets {
+		labels.entry(target as u16).or_insert_with(|| format!("loc_{}", target));
+	}
+	return Disassembly { map, functions, labels, comments: HashMap::new() };
+}
+
+fn addr_list(addrs:&[u16]) -> String {
+	return addrs.iter().map(|addr| addr.to_string()).collect::<Vec<String>>().join(" ");
+}
+
+fn function_header(function:&Function) -> String {
+	let mut header = format!("; function {}-{}", function.entry, function.end);
+	if !function.callers.is_empty() {
+		header.push_str(&format!(", called from {}", addr_list(&function.callers)));
+	}
+	if !function.references.is_empty() {
+		header.push_str(&format!(", address loaded at {}", addr_list(&function.references)));
+	}
+	return header;
+}
+
+// the instruction at addr with its jump, call or memory address operand
+// replaced by a label. SET and PUSH operands are only replaced where they
+// load a callback.
+fn labelled_instruction(program:&[u16], addr:usize, labels:&HashMap<u16, String>, loads_callback:bool) -> Option<String> {
+	let opcode = program[addr];
+	let operand = match opcode {
+		2 | 6 | 16 | 17 => 1,
+		1 | 7 | 8 | 15 => 2,
+		_ => return None,
+	};
+	if (opcode == 1 || opcode == 2) && !loads_callback {
+		return None;
+	}
+	let label = labels.get(program.get(addr + operand)?)?;
+	let (name, operands) = OPCODES[opcode as usize];
+	let mut text = name.to_string();
+	for n in 1..=operands {
+		text.push(' ');
+		if n == operand {
+			text.push_str(label);
+		}
+		else {
+			text.push_str(&decompiler_val(program, addr + n));
+		}
+	}
+	return Some(append_with_tabs(text, 6, format!("#{}", addr)));
+}
+
+fn with_comment(line:String, comment:Option<&String>) -> String {
+	return match comment {
+		Some(comment) => format!("{} ; {}", line, comment),
+		None => line,
+	};
+}
+
+// the instruction at addr for display, with labels for its operands and any
+// comment, and the number of words it takes
+pub fn instruction_line(program:&[u16], addr:usize, labels:&HashMap<u16, String>, comments:&HashMap<u16, String>, loads_callback:bool) -> (String, usize) {
+	let (line, len) = decompile_instruction(program, addr);
+	let line = labelled_instruction(program, addr, labels, loads_callback).unwrap_or(line);
+	return (with_comment(line, comments.get(&(addr as u16))), len);
+}
+
+fn data_line(program:&[u16], addr:usize, len:usize) -> String {
+	let words:Vec<String> = program[addr..addr + len].iter().map(|word| word.to_string()).collect();
+	return append_with_tabs(format!(".data {}", words.join(" ")), 6, format!("#{}", addr));
+}
+
+// list the program with code as labelled instructions under function
+// headers, and everything else as .data blocks. The listing assembles back to
+// the same program.
+pub fn disassemble(program:&[u16], disassembly:&Disassembly) -> Vec<String> {
+	let map = &disassembly.map;
+	let headers:HashMap<u16, String> = disassembly.functions.iter().map(|function| (function.entry, function_header(function))).collect();
+	let callback_loads = disassembly.callback_loads();
+	let mut lines = Vec::new();
+	let mut addr = 0;
+	while addr < program.len() {
+		if map.starts[addr] {
+			if let Some(header) = headers.get(&(addr as u16)) {
+				lines.push(String::new());
+				lines.push(header.clone());
+			}
+			if let Some(label) = disassembly.labels.get(&(addr as u16)) {
+				lines.push(format!("{}:", label));
+			}
+			let (line, len) = instruction_line(program, addr, &disassembly.labels, &disassembly.comments, callback_loads.contains(&(addr as u16)));
+			lines.push(line);
+			addr += len;
+			continue;
+		}
+		if let Some(label) = disassembly.labels.get(&(addr as u16)) {
+			lines.push(format!("{}:", label));
+		}
+		// a data block ends at the next instruction or the next address with a label or comment
+		let mut len = 1;
+		while addr + len < program.len() && !map.starts[addr + len] && len < DATA_LINE_WORDS {
+			let next = (addr + len) as u16;
+			if disassembly.labels.contains_key(&next) || disassembly.comments.contains_key(&next) {
+				break;
+			}
+			len += 1;
+		}
+		lines.push(with_comment(data_line(program, addr, len), disassembly.comments.get(&(addr as u16))));
+		addr += len;
+	}
+	return lines;
+}
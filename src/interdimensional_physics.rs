@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 use std::thread;
 use std::sync::{Arc,Mutex};
+use super::synacor_vm::HookAction;
 
 const U15_MAX: u16 = 32767;
 const U15_MOD: u16 = 32768;
 const REG_N:u16 = 8;
 
-fn decompiler_val(program:&Vec<u16>, index:usize) -> String {
+pub(crate) fn decompiler_val(program:&[u16], index:usize) -> String {
 	if index >= program.len() {
 		return "Invalid index".to_string();
 	}
@@ -21,7 +22,7 @@ fn decompiler_val(program:&Vec<u16>, index:usize) -> String {
 		return format!("INVALID {}", val);
 	}
 }
-fn append_with_tabs(input:String, tab_pos:usize, append:String) -> String {
+pub(crate) fn append_with_tabs(input:String, tab_pos:usize, append:String) -> String {
 	let tab_len = 4;
 	if tab_len * tab_pos < input.len() {
 		return format!("{}\t{}", input, append);
@@ -29,132 +30,70 @@ fn append_with_tabs(input:String, tab_pos:usize, append:String) -> String {
 	let mut result = input.clone();
 	let mut pos = result.len();
 	while pos < tab_pos * tab_len {
-		result.push_str("\t");
+		result.push('\t');
 		pos += tab_len;
 	}
 	return format!("{}{}", result, append);
 }
-pub fn decompile(program:&Vec<u16>) -> Vec<String> {
+pub fn decompile(program:&[u16]) -> Vec<String> {
 	let mut index = 0;
 	let mut lines:Vec<String> = Vec::new();
-	let tab_pos = 6;
 	while index < program.len() {
-		match program[index] {
-			0 => {
-					lines.push(append_with_tabs(format!("HALT"), tab_pos, format!("#{}", index)));
-					index += 1;
-				},
-			1 => {
-					lines.push(append_with_tabs(format!("SET {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)));
-					index += 3;
-				},
-			2 => {
-					lines.push(append_with_tabs(format!("PUSH {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)));
-					index += 2;
-				},
-			3 => {
-					lines.push(append_with_tabs(format!("POP {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)));
-					index += 2;
-				},
-			4 => {
-					lines.push(append_with_tabs(format!("EQ {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			5 => {
-					lines.push(append_with_tabs(format!("GT {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			6 => {
-					lines.push(append_with_tabs(format!("JMP {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)));
-					index += 2;
-				},
-			7 => {
-					lines.push(append_with_tabs(format!("JT {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)));
-					index += 3;
-				},
-			8 => {
-					lines.push(append_with_tabs(format!("JF {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)));
-					index += 3;
-				},
-			9 => {
-					lines.push(append_with_tabs(format!("ADD {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			10 => {
-					lines.push(append_with_tabs(format!("MULT {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			11 => {
-					lines.push(append_with_tabs(format!("MOD {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			12 => {
-					lines.push(append_with_tabs(format!("AND {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			13 => {
-					lines.push(append_with_tabs(format!("OR {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)));
-					index += 4;
-				},
-			14 => {
-					lines.push(append_with_tabs(format!("NOT {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)));
-					index += 3;
-				},
-			15 => {
-					lines.push(append_with_tabs(format!("RMEM {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)));					
-					index += 3;
-				},
-			16 => {
-					lines.push(append_with_tabs(format!("WMEM {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)));					
-					index += 3;
-				},
-			17 => {
-					lines.push(append_with_tabs(format!("CALL {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)));
-					index += 2;
-				},
-			18 => {
-					lines.push(append_with_tabs(format!("RET"), tab_pos, format!("#{}", index)));
-					index += 1;
-				},
-			19 =>  {
-					let val = program[index+1];
-					if val <= 255 {
-						if val == 10 {
-							lines.push(append_with_tabs(format!("OUT {}\t(LF)", decompiler_val(program, index + 1)), tab_pos - 1, format!("#{}", index)));		
-						}
-						else {
-							lines.push(append_with_tabs(format!("OUT {}\t({})", decompiler_val(program, index + 1), val as u8 as char), tab_pos, format!("#{}", index)));
-						}
-						
-					}
-					else {
-						lines.push(append_with_tabs(format!("OUT {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)));
-					}
-					index += 2;
-				},
-			20 =>  {
-					lines.push(append_with_tabs(format!("IN {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)));
-					index += 2;
-				},
-			21 => {
-					lines.push(append_with_tabs(format!("NOOP"), tab_pos, format!("#{}", index)));
-					index += 1;
-				},
-			_ => {
-					lines.push(append_with_tabs(format!("DATA? {}", program[index]), tab_pos, format!("#{}", index)));
-					index += 1;
-				}
-		}
+		let (line, len) = decompile_instruction(program, index);
+		lines.push(line);
+		index += len;
 	}
 	return lines;
 }
+// decompile the single instruction at index. Returns the text and the number of words it occupies
+pub fn decompile_instruction(program:&[u16], index:usize) -> (String, usize) {
+	let tab_pos = 6;
+	let (line, len) = match program[index] {
+		0 => (append_with_tabs("HALT".to_string(), tab_pos, format!("#{}", index)), 1),
+		1 => (append_with_tabs(format!("SET {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)), 3),
+		2 => (append_with_tabs(format!("PUSH {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)), 2),
+		3 => (append_with_tabs(format!("POP {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)), 2),
+		4 => (append_with_tabs(format!("EQ {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		5 => (append_with_tabs(format!("GT {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		6 => (append_with_tabs(format!("JMP {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)), 2),
+		7 => (append_with_tabs(format!("JT {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)), 3),
+		8 => (append_with_tabs(format!("JF {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)), 3),
+		9 => (append_with_tabs(format!("ADD {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		10 => (append_with_tabs(format!("MULT {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		11 => (append_with_tabs(format!("MOD {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		12 => (append_with_tabs(format!("AND {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		13 => (append_with_tabs(format!("OR {} {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2), decompiler_val(program, index + 3)), tab_pos, format!("#{}", index)), 4),
+		14 => (append_with_tabs(format!("NOT {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)), 3),
+		15 => (append_with_tabs(format!("RMEM {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)), 3),
+		16 => (append_with_tabs(format!("WMEM {} {}", decompiler_val(program, index + 1), decompiler_val(program, index + 2)), tab_pos, format!("#{}", index)), 3),
+		17 => (append_with_tabs(format!("CALL {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)), 2),
+		18 => (append_with_tabs("RET".to_string(), tab_pos, format!("#{}", index)), 1),
+		19 => {
+				let val = *program.get(index+1).unwrap_or(&U15_MOD);
+				let line = if val == 10 {
+					append_with_tabs(format!("OUT {}\t(LF)", decompiler_val(program, index + 1)), tab_pos - 1, format!("#{}", index))
+				}
+				else if val <= 255 {
+					append_with_tabs(format!("OUT {}\t({})", decompiler_val(program, index + 1), val as u8 as char), tab_pos, format!("#{}", index))
+				}
+				else {
+					append_with_tabs(format!("OUT {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index))
+				};
+				(line, 2)
+			},
+		20 => (append_with_tabs(format!("IN {}", decompiler_val(program, index + 1)), tab_pos, format!("#{}", index)), 2),
+		21 => (append_with_tabs("NOOP".to_string(), tab_pos, format!("#{}", index)), 1),
+		_ => (append_with_tabs(format!("DATA? {}", program[index]), tab_pos, format!("#{}", index)), 1),
+	};
+	return (line, len);
+}
 
 pub fn thread_6027_single(r7:u16) -> bool {
 	let mut cache:HashMap<u32, u16> = HashMap::new();
 	for r0 in 0..=4 {
 		for r1 in 0..=U15_MAX {
-			let compute_result = compute_6027(r0 as u16,r1 as u16,r7 as u16, &mut cache);
-			cache.insert(tuple_key(r0 as u16, r1 as u16), compute_result);
+			let compute_result = compute_6027(r0 as u16,r1,r7, &mut cache);
+			cache.insert(tuple_key(r0 as u16, r1), compute_result);
 			if r1 == 1 && r0 == 4 {
 				break;
 			}
@@ -166,12 +105,12 @@ pub fn thread_6027_single(r7:u16) -> bool {
 pub fn thread_6027(lo:u16, hi:u16) -> u16 {
 	let mut r7:u16 = lo;
 	while r7 <= hi {
-		if thread_6027_single(r7 as u16) {
-			return r7 as u16;
+		if thread_6027_single(r7) {
+			return r7;
 		}
 		r7+=1;
 	}
-	return 0 as u16;
+	return 0;
 }
 
 // multi-thread search for r7
@@ -189,13 +128,12 @@ pub fn multithreaded_6027_result() -> u16 {
 			let arc_clone = Arc::clone(&arc);
 			let handle = thread::spawn(move || {
 				let lo:u16 = index;
-				let hi:u16;
-				if lo + range > U15_MAX {
-					hi = U15_MAX;
+				let hi:u16 = if lo + range > U15_MAX {
+					U15_MAX
 				}
 				else {
-					hi = lo + range;
-				}
+					lo + range
+				};
 				let result = thread_6027(lo, hi);
 				if result != 0 {
 					let mut r7_result = arc_clone.lock().unwrap();
@@ -203,14 +141,14 @@ pub fn multithreaded_6027_result() -> u16 {
 				}
 			});
 			handles.push(handle);
-			index = index + range;
+			index += range;
 			if index > U15_MAX {
 				done = true;
 				break;
 			}
 		}
 		// execute batch
-		while handles.len() > 0 {
+		while !handles.is_empty() {
 			let handle = handles.remove(0);
 			handle.join().unwrap();
 		}
@@ -295,24 +233,26 @@ pub fn physics_analysis(vm:&mut super::synacor_vm::SynacorVM, skip_precomputed:b
 	println!("Activating teleporter...");
 	let _ = vm.output_line(true);
 	
-	vm.set_input_line("use teleporter");
-	loop {	
-		if vm.get_mem_ptr() == 5489 {
-			println!("Bypassing teleporter activation check...");
-			vm.set_register(0, 6);
-			vm.set_register(1, 5);
-			vm.set_mem_ptr(5491);
-			println!("Teleporting...");
-			break();
-		}
-		vm.execute_once();
-		if vm.is_halted() || vm.is_awaiting_input() {
-			break;
+	// replace the confirmation routine at 6027 with the result it would eventually
+	// produce. The teleporter checks for r0 == 6 when it returns. The routine
+	// calls itself, so only the call from the teleporter at 5489, which returns
+	// to 5491, is replaced.
+	vm.register_hook(6027, |state| {
+		if state.stack().last() != Some(&5491) {
+			return HookAction::Continue;
 		}
-	}
+		println!("Bypassing teleporter activation check...");
+		state.set_register(0, 6);
+		state.set_register(1, 5);
+		println!("Teleporting...");
+		return HookAction::Return;
+	});
+	vm.set_input_line("use teleporter");
+	vm.run_until(|vm| vm.get_mem_ptr() == 6027);
 	// clear output
 	let _ = vm.output_line(true);
 	vm.execute();
+	vm.remove_hook(6027);
 	// arriving on the beach yields challenge code #7
 	println!("{}", vm.output_line(true));
 	return true;
@@ -0,0 +1,16 @@
+// the codebase favours explicit returns
+#![allow(clippy::needless_return)]
+
+pub mod synacor_vm;
+pub mod twisty_passages;
+pub mod strange_monument;
+pub mod interdimensional_physics;
+pub mod orb_vault;
+pub mod debugger;
+pub mod profiler;
+pub mod benchmark;
+pub mod transcript;
+pub mod assembler;
+pub mod disassembler;
+pub mod symbols;
+pub mod cfg;
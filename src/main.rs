@@ -1,8 +1,7 @@
-pub mod synacor_vm;
-pub mod twisty_passages;
-pub mod strange_monument;
-pub mod interdimensional_physics;
-pub mod orb_vault;
+// the codebase favours explicit returns
+#![allow(clippy::needless_return)]
+
+use synacor_challenge::{synacor_vm, twisty_passages, strange_monument, interdimensional_physics, orb_vault, debugger, profiler, benchmark, transcript, assembler, disassembler, symbols, cfg};
 
 use clap::{Arg, Command};
 use std::process;
@@ -10,29 +9,38 @@ use std::io::prelude::*;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// where shutdown() writes the profile report, if profiling
+static PROFILE_PATH: OnceLock<String> = OnceLock::new();
+// where shutdown() writes the call graph, if requested
+static CALLGRAPH_PATH: OnceLock<String> = OnceLock::new();
+// records the player's input in interactive mode, if requested
+static TRANSCRIPT: OnceLock<transcript::Recorder> = OnceLock::new();
 
 
 fn read_bin(path:&String) -> Vec<u16>
 {
-	let file_result = File::open(&path);
-	if !file_result.is_ok() {
+	let file_result = File::open(path);
+	if file_result.is_err() {
 		println!("Unable to open file at path {}", path);
 		process::exit(0);
 	}
-	let metadata_result = fs::metadata(&path);
-	if !metadata_result.is_ok() {
+	let metadata_result = fs::metadata(path);
+	if metadata_result.is_err() {
 		println!("Unable to read metadata for file at path {}", path);
 		process::exit(0);
 	}
 	let mut file = file_result.unwrap();
 	let metadata = metadata_result.unwrap();
     let mut buff = vec![0; metadata.len() as usize];
-	let file_read_result = file.read(&mut buff);
-	if !file_read_result.is_ok() {
-		println!("An error occurred while reading the file at path {}: {}", path, file_read_result.unwrap_err());
+	if let Err(err) = file.read(&mut buff) {
+		println!("An error occurred while reading the file at path {}: {}", path, err);
 		process::exit(0);
 	}
-	let mut buff2:Vec<u16> = vec![0; ((metadata.len()/2) + 1) as usize];
+	// an odd trailing byte becomes the low byte of a final word
+	let mut buff2:Vec<u16> = vec![0; metadata.len().div_ceil(2) as usize];
 	let mut index = 0;
 	while index < buff.len() {
 		let byte1 = buff[index];
@@ -41,10 +49,10 @@ fn read_bin(path:&String) -> Vec<u16>
 			byte2 = buff[index + 1];
 		}
 		let mut val:u16 = 0;
-		val = val | (byte1 as u16);
-		val = val | (byte2 as u16) << 8;
+		val |= byte1 as u16;
+		val |= (byte2 as u16) << 8;
 		buff2[index/2] = val;
-		index = index + 2;
+		index += 2;
 	}
 	return buff2;
 }
@@ -53,48 +61,115 @@ fn main() {
 	// don't forget to examine the arch-spec file for challenge code #1
 	
 	let args = Command::new("synacor-challenge")
-					.arg(Arg::new("INPUT").help("Your challenge.bin file").required(true).index(1))
+					.arg(Arg::new("INPUT").help("Your challenge.bin file").required_unless_present("diff").index(1))
 					.arg(Arg::new("interactive").help("Disables autosolving and runs the challenge binary in interactive terminal mode.").short('i'))
 					.arg(Arg::new("dump").help("Export a decompiled version of the challenge binary to text file").short('d').value_name("FILE").takes_value(true))
+					.arg(Arg::new("linear").help("Decompile with -d by decoding every word in turn instead of following control flow from the entry point").long("linear").requires("dump"))
+					.arg(Arg::new("cfg").help("Write a control-flow graph of every function, and the call graph as calls.dot, to Graphviz DOT files in a directory, then exit").long("cfg").value_name("DIR").takes_value(true))
+					.arg(Arg::new("symbols").help("Names and comments for addresses, shown by -d, --cfg and the debugger").long("symbols").value_name("FILE").takes_value(true).conflicts_with("linear"))
 					.arg(Arg::new("teleportersearch").help("Enables the search for a teleporter setting rather than using a precomputed solution.").short('t').value_name("SEARCH_TYPE").possible_values(["single", "parallel"]))
+					.arg(Arg::new("debug").help("Runs the challenge binary under the interactive debugger instead of autosolving").short('g'))
+					.arg(Arg::new("strict").help("Trap on every violation of the architecture spec instead of only what the challenge binary needs").long("strict"))
+					.arg(Arg::new("loadstate").help("Start from a previously saved VM state instead of the start of the game. Runs in interactive mode unless --resume is given.").long("load-state").value_name("FILE").takes_value(true))
+					.arg(Arg::new("resume").help("Resume autosolving after the named stage of a state loaded with --load-state").long("resume").value_name("STAGE").possible_values(STAGES).requires("loadstate"))
+					.arg(Arg::new("savestate").help("Save the VM state to a file when control passes to interactive mode").long("save-state").value_name("FILE").takes_value(true))
+					.arg(Arg::new("savestages").help("Save the VM state to <DIR>/<STAGE>.state after each autosolve stage").long("save-stages").value_name("DIR").takes_value(true))
+					.arg(Arg::new("trace").help("Record a trace of every executed instruction to a file").long("trace").value_name("FILE").takes_value(true))
+					.arg(Arg::new("traceformat").help("Trace output format").long("trace-format").value_name("FORMAT").possible_values(["text", "binary"]).default_value("text").requires("trace"))
+					.arg(Arg::new("tracerange").help("Only trace instructions at addresses LO to HI inclusive").long("trace-range").value_name("LO-HI").takes_value(true).requires("trace"))
+					.arg(Arg::new("traceskip").help("Number of executed instructions to skip before tracing").long("trace-skip").value_name("N").takes_value(true).requires("trace"))
+					.arg(Arg::new("tracelimit").help("Maximum number of instructions to trace").long("trace-limit").value_name("N").takes_value(true).requires("trace"))
+					.arg(Arg::new("profile").help("Count executed instructions per address, opcode and function and write a report to a file on exit").long("profile").value_name("FILE").takes_value(true))
+					.arg(Arg::new("callgraph").help("Write the guest call graph with call counts and recursion depths to a Graphviz DOT file on exit").long("callgraph").value_name("FILE").takes_value(true))
+					.arg(Arg::new("transcript").help("Append every line of input typed in interactive mode, with a timestamp, to a transcript file").long("transcript").value_name("FILE").takes_value(true))
+					.arg(Arg::new("replay").help("Replay the input in a transcript file instead of autosolving, then exit, or continue in interactive mode with -i").long("replay").value_name("FILE").takes_value(true).conflicts_with("resume"))
+					.arg(Arg::new("diff").help("List every memory word, register, stack entry and pc that differs between two saved VM states, then exit").long("diff").value_names(&["A", "B"]).number_of_values(2))
+					.arg(Arg::new("bench").help("Time the interpreter on the challenge binary with and without the decoded instruction cache, then exit").long("bench").value_name("N").takes_value(true).min_values(0).require_equals(true).default_missing_value("50000000"))
+					.subcommand(Command::new("asm").about("Assemble Synacor assembly text into a challenge binary")
+						.arg(Arg::new("SOURCE").help("Assembly source, such as a file exported with -d").required(true).index(1))
+						.arg(Arg::new("OUTPUT").help("Binary file to write").required(true).index(2)))
+					.subcommand_negates_reqs(true)
 					.get_matches();
 	
+	// optional: assemble a source file then exit
+	if let Some(asm_args) = args.subcommand_matches("asm") {
+		assemble(asm_args.value_of("SOURCE").unwrap(), asm_args.value_of("OUTPUT").unwrap());
+		process::exit(0);
+	}
+	
+	// optional: compare two saved states then exit
+	if let Some(paths) = args.values_of("diff") {
+		let paths:Vec<&str> = paths.collect();
+		let mut states:Vec<synacor_vm::SynacorVM> = Vec::new();
+		for path in paths {
+			let mut vm = synacor_vm::SynacorVM::new(false);
+			if let Err(err) = vm.load_state_file(path) {
+				println!("Unable to load state {}: {}", path, err);
+				process::exit(0);
+			}
+			states.push(vm);
+		}
+		print!("{}", states[0].diff(&states[1]));
+		process::exit(0);
+	}
+	
 	// read the binary
 	let bin_path = args.value_of_t("INPUT").unwrap_or_else(|e| e.exit());
 	let binary = read_bin(&bin_path);
 	
+	// optional: user names and comments for the decompiler and debugger
+	let symbols = match args.value_of("symbols") {
+		Some(symbols_path) => match symbols::Symbols::read(symbols_path) {
+			Ok(symbols) => symbols,
+			Err(err) => {
+				println!("Unable to load symbols {}: {}", symbols_path, err);
+				process::exit(0);
+			},
+		},
+		None => symbols::Symbols::default(),
+	};
+	
 	// optional: decompile and dump the binary then exit
 	if args.is_present("dump") {
 		let dump_path:&str = args.value_of("dump").unwrap();
 		println!("Exporting decompiled binary to {}", dump_path);
-		let decompiled = interdimensional_physics::decompile(&binary);
+		let decompiled = if args.is_present("linear") {
+			interdimensional_physics::decompile(&binary)
+		}
+		else {
+			let mut disassembly = disassembler::analyze(&binary, &[0]);
+			disassembly.apply_symbols(&symbols);
+			disassembler::disassemble(&binary, &disassembly)
+		};
 		let mut file = File::create(dump_path).unwrap();
-		for i in 0..decompiled.len() {
-			writeln!(&mut file, "{}", decompiled[i]).unwrap();
+		for line in decompiled.iter() {
+			writeln!(&mut file, "{}", line).unwrap();
 		}
 		process::exit(0);
 	}
 	
-	// optional: run in interactive mode
-	let interactive:bool;
-	if args.is_present("interactive") {
-		interactive = true;
+	// optional: write control-flow graphs then exit
+	if let Some(cfg_dir) = args.value_of("cfg") {
+		write_cfg(&binary, &symbols, cfg_dir);
+		process::exit(0);
 	}
-	else {
-		interactive = false;
+	
+	// optional: benchmark the interpreter then exit
+	if args.is_present("bench") {
+		let max_instructions:u64 = args.value_of_t("bench").unwrap_or_else(|e| e.exit());
+		print!("{}", benchmark::compare(&binary, max_instructions));
+		process::exit(0);
 	}
 	
+	// optional: run in interactive mode
+	let interactive:bool = args.is_present("interactive");
+	
 	// optional: enable teleporter r7 search
 	let teleporter_search:bool;
 	let teleporter_search_parallel:bool;
 	if args.is_present("teleportersearch") {
 		teleporter_search = true;
-		if args.value_of("teleportersearch").unwrap() == "single" {
-			teleporter_search_parallel = false;
-		}
-		else {
-			teleporter_search_parallel = true;
-		}
+		teleporter_search_parallel = args.value_of("teleportersearch").unwrap() != "single";
 	}
 	else {
 		teleporter_search = false;
@@ -103,73 +178,286 @@ fn main() {
 	
 	// initialize vm and load binary into memory
 	let mut vm:synacor_vm::SynacorVM = synacor_vm::SynacorVM::new(false);
-	let load_mem_result = vm.load_mem(binary);
-	if !load_mem_result.is_ok() {
-		println!("Load program error: {}", load_mem_result.unwrap_err())
-	}	
-	// run initial startup and self test
-	vm.execute();
-	// the output of startup and the self test yields challenge codes #2 and #3
-	println!("{}",vm.output_line(true));
+	if args.is_present("strict") {
+		vm.set_strictness(synacor_vm::Strictness::Strict);
+	}
+	if let Err(err) = vm.load_mem(binary) {
+		println!("Load program error: {}", err)
+	}
+	let save_state_path = args.value_of("savestate");
+	let save_stages_dir = args.value_of("savestages");
 	
-	if interactive {
-		vm.set_interactive(true);	
-		loop {
-			vm.execute();
-			if vm.is_halted() {
-				process::exit(0);
-			}
+	// optional: pick up from a saved state rather than the start of the game
+	let mut first_stage = 0;
+	if args.is_present("loadstate") {
+		let load_path = args.value_of("loadstate").unwrap();
+		if let Err(err) = vm.load_state_file(load_path) {
+			println!("Unable to load state from {}: {}", load_path, err);
+			process::exit(0);
 		}
+		println!("Loaded state from {}", load_path);
 	}
 	
-	println!("Suspending interactive mode. Beginning automatic traversal.");
-	// challenge code #4 appears here when taking and using the tablet
-	play_to_twisty_passages(&mut vm);
-	println!("Automatic traversal has reached the maze of twisty little passages, all alike.");
-	println!("Solving the maze of twisty little passages using the power of the multiverse...");
-	// solving the maze of twisty little passages yields challenge code #5
-	if !twisty_passages::solve(&mut vm) {
-		println!("Unable to solve the maze of twisty little passages");
-		process::exit(0);
+	// optional: trace execution from here on
+	if args.is_present("trace") {
+		let trace_path = args.value_of("trace").unwrap();
+		let config = trace_config(&args);
+		if let Err(err) = vm.start_trace_file(trace_path, config) {
+			println!("Unable to start trace to {}: {}", trace_path, err);
+			process::exit(0);
+		}
 	}
-	println!("The maze of twisty passages has been solved. The can has been located.");
-	println!("Resuming automatic traversal...");
 	
-	play_to_strange_monument(&mut vm);
+	// optional: profile execution from here on
+	if let Some(profile_path) = args.value_of("profile") {
+		PROFILE_PATH.set(profile_path.to_string()).unwrap();
+		vm.start_profile();
+	}
+	if let Some(callgraph_path) = args.value_of("callgraph") {
+		CALLGRAPH_PATH.set(callgraph_path.to_string()).unwrap();
+		// only count calls made from here on
+		vm.reset_call_graph();
+	}
 	
-	println!("Automatic traversal has reached the strange monument.");
-	println!("Thinking about the solution to the strange monument...");
-	if !strange_monument::solve(&mut vm) {
-		println!("Unable to solve the mystery of the strange monument");
-		process::exit(0);
+	// optional: record player input from here on
+	if let Some(transcript_path) = args.value_of("transcript") {
+		match transcript::Recorder::create(transcript_path) {
+			Ok(recorder) => { let _ = TRANSCRIPT.set(recorder); },
+			Err(err) => {
+				println!("Unable to open transcript {}: {}", transcript_path, err);
+				shutdown(&mut vm);
+			},
+		}
+	}
+	
+	// optional: hand the vm to the debugger from the start of the program or the loaded state
+	if args.is_present("debug") {
+		debugger::run(&mut vm, &symbols);
+		shutdown(&mut vm);
+	}
+	
+	if args.is_present("loadstate") {
+		match args.value_of("resume") {
+			Some(stage) => first_stage = STAGES.iter().position(|s| *s == stage).unwrap() + 1,
+			None if args.is_present("replay") => (),
+			None => run_interactive(&mut vm, save_state_path),
+		}
+	}
+	else {
+		// run initial startup and self test
+		vm.execute();
+		// the output of startup and the self test yields challenge codes #2 and #3
+		println!("{}",vm.output_line(true));
 	}
-	println!("The mystery of the strange monument has been solved. The way forward has opened.");
-	println!("Resuming automatic traversal...");
 	
-	// reaching Synacor HQ yields challenge code #6
-	play_to_synacor_hq(&mut vm);
+	// optional: replay a transcript in place of autosolving
+	if let Some(replay_path) = args.value_of("replay") {
+		let entries = match transcript::read(replay_path) {
+			Ok(entries) => entries,
+			Err(err) => {
+				println!("Unable to read transcript {}: {}", replay_path, err);
+				shutdown(&mut vm);
+			},
+		};
+		let replayed = transcript::replay(&mut vm, &entries, TRANSCRIPT.get());
+		if replayed < entries.len() {
+			println!("The game stopped taking input after {} of {} transcript lines", replayed, entries.len());
+		}
+		if let Some(reason) = vm.halt_reason() {
+			report_halt(reason);
+		}
+		else if interactive {
+			run_interactive(&mut vm, save_state_path);
+		}
+		shutdown(&mut vm);
+	}
+	
+	if interactive {
+		run_interactive(&mut vm, save_state_path);
+	}
 	
-	println!("Automatic traversal has reached Synacor Headquarters.");
-	println!("Delving into the secrets of the universe...");
-	// activating the teleporter correctly to reach the second destination yields challenge code #7
-	if !interdimensional_physics::physics_analysis(&mut vm, teleporter_search, teleporter_search_parallel) {
-		println!("Unable to solve the secrets of the universe...")
-	}
-	println!("The secrets of the universe have been illuminated. The teleporter destination has been reached.");
-	println!("Resuming automatic traversal...");
-	play_to_vault(&mut vm);
-	println!("Automatic traversal has reached the vault antechamber.");
-	if !orb_vault::solve(&mut vm) {
-		println!("Like the expedition before you, the mystery of the vault escapes your grasp.")
+	println!("Suspending interactive mode. Beginning automatic traversal.");
+	for stage in STAGES.iter().skip(first_stage) {
+		run_stage(&mut vm, stage, teleporter_search, teleporter_search_parallel);
+		if let Some(dir) = save_stages_dir {
+			let path = Path::new(dir).join(format!("{}.state", stage));
+			match vm.save_state_file(path.to_str().unwrap()) {
+				Ok(_) => println!("Saved state after stage {} to {}", stage, path.display()),
+				Err(err) => println!("Unable to save state to {}: {}", path.display(), err),
+			}
+		}
 	}
 	println!("Resuming interactive mode...");
-	vm.set_interactive(true);	
+	run_interactive(&mut vm, save_state_path);
+}
+fn assemble(source_path:&str, output_path:&str) {
+	let source = match fs::read_to_string(source_path) {
+		Ok(source) => source,
+		Err(err) => {
+			println!("Unable to read {}: {}", source_path, err);
+			return;
+		},
+	};
+	match assembler::assemble(&source) {
+		Ok(words) => match fs::write(output_path, assembler::to_bytes(&words)) {
+			Ok(_) => println!("Assembled {} words to {}", words.len(), output_path),
+			Err(err) => println!("Unable to write {}: {}", output_path, err),
+		},
+		Err(err) => println!("{}: {}", source_path, err),
+	}
+}
+// one DOT file per function, named after its label, and calls.dot
+fn write_cfg(binary:&[u16], symbols:&symbols::Symbols, dir:&str) {
+	if let Err(err) = fs::create_dir_all(dir) {
+		println!("Unable to create {}: {}", dir, err);
+		return;
+	}
+	let mut disassembly = disassembler::analyze(binary, &[0]);
+	disassembly.apply_symbols(symbols);
+	let blocks = cfg::basic_blocks(binary, &disassembly);
+	let mut files = vec![(Path::new(dir).join("calls.dot"), cfg::call_graph_dot(binary, &disassembly, &blocks))];
+	for function in disassembly.functions.iter() {
+		let name = disassembly.labels.get(&function.entry).cloned().unwrap_or_else(|| function.entry.to_string());
+		files.push((Path::new(dir).join(format!("{}.dot", name)), cfg::function_dot(binary, &disassembly, &blocks, function.entry)));
+	}
+	for (path, dot) in files {
+		if let Err(err) = fs::write(&path, dot) {
+			println!("Unable to write {}: {}", path.display(), err);
+			return;
+		}
+	}
+	println!("Wrote control-flow graphs of {} functions to {}", disassembly.functions.len(), dir);
+}
+// autosolve stages in order. A state saved after a stage can be resumed with --resume <STAGE>
+const STAGES: [&str; 5] = ["twisty", "monument", "hq", "teleporter", "vault"];
+
+fn run_stage(vm:&mut synacor_vm::SynacorVM, stage:&str, teleporter_search:bool, teleporter_search_parallel:bool) {
+	match stage {
+		"twisty" => {
+			// challenge code #4 appears here when taking and using the tablet
+			play_to_twisty_passages(vm);
+			println!("Automatic traversal has reached the maze of twisty little passages, all alike.");
+			println!("Solving the maze of twisty little passages using the power of the multiverse...");
+			// solving the maze of twisty little passages yields challenge code #5
+			if !twisty_passages::solve(vm) {
+				println!("Unable to solve the maze of twisty little passages");
+				shutdown(vm);
+			}
+			println!("The maze of twisty passages has been solved. The can has been located.");
+			println!("Resuming automatic traversal...");
+		},
+		"monument" => {
+			play_to_strange_monument(vm);
+			
+			println!("Automatic traversal has reached the strange monument.");
+			println!("Thinking about the solution to the strange monument...");
+			if !strange_monument::solve(vm) {
+				println!("Unable to solve the mystery of the strange monument");
+				shutdown(vm);
+			}
+			println!("The mystery of the strange monument has been solved. The way forward has opened.");
+			println!("Resuming automatic traversal...");
+		},
+		"hq" => {
+			// reaching Synacor HQ yields challenge code #6
+			play_to_synacor_hq(vm);
+			
+			println!("Automatic traversal has reached Synacor Headquarters.");
+		},
+		"teleporter" => {
+			println!("Delving into the secrets of the universe...");
+			// activating the teleporter correctly to reach the second destination yields challenge code #7
+			if !interdimensional_physics::physics_analysis(vm, teleporter_search, teleporter_search_parallel) {
+				println!("Unable to solve the secrets of the universe...")
+			}
+			println!("The secrets of the universe have been illuminated. The teleporter destination has been reached.");
+			println!("Resuming automatic traversal...");
+		},
+		"vault" => {
+			play_to_vault(vm);
+			println!("Automatic traversal has reached the vault antechamber.");
+			if !orb_vault::solve(vm) {
+				println!("Like the expedition before you, the mystery of the vault escapes your grasp.")
+			}
+		},
+		_ => panic!("Unknown stage {}", stage),
+	}
+}
+fn run_interactive(vm:&mut synacor_vm::SynacorVM, save_state_path:Option<&str>) -> ! {
+	if let Some(path) = save_state_path {
+		match vm.save_state_file(path) {
+			Ok(_) => println!("Saved state to {}", path),
+			Err(err) => println!("Unable to save state to {}: {}", path, err),
+		}
+	}
+	vm.set_interactive(true);
+	if let Some(recorder) = TRANSCRIPT.get() {
+		vm.set_input(Box::new(transcript::RecordingInput::new(Box::new(synacor_vm::StdinInput), recorder.clone())));
+	}
 	loop {
-		vm.execute();
-		if vm.is_halted() {
-			process::exit(0);
+		match vm.execute() {
+			synacor_vm::StopReason::Halted(reason) => {
+				report_halt(&reason);
+				shutdown(vm);
+			},
+			// stdin has been closed
+			synacor_vm::StopReason::AwaitingInput => shutdown(vm),
+			_ => (),
+		}
+	}
+}
+fn trace_config(args:&clap::ArgMatches) -> synacor_vm::TraceConfig {
+	let mut config = synacor_vm::TraceConfig::default();
+	if args.value_of("traceformat") == Some("binary") {
+		config.format = synacor_vm::TraceFormat::Binary;
+	}
+	if let Some(range) = args.value_of("tracerange") {
+		let bounds:Vec<&str> = range.split('-').collect();
+		let lo = bounds[0].parse::<u16>();
+		let hi = bounds.get(1).map(|b| b.parse::<u16>());
+		match (lo, hi) {
+			(Ok(lo), Some(Ok(hi))) if bounds.len() == 2 => config.range = Some((lo, hi)),
+			_ => {
+				println!("Invalid trace range {}, expected LO-HI", range);
+				process::exit(0);
+			}
 		}
-	}	
+	}
+	if args.is_present("traceskip") {
+		config.skip = args.value_of_t("traceskip").unwrap_or_else(|e| e.exit());
+	}
+	if args.is_present("tracelimit") {
+		config.limit = Some(args.value_of_t("tracelimit").unwrap_or_else(|e| e.exit()));
+	}
+	return config;
+}
+// exit the process, finishing any trace, profile or call graph in progress first
+fn shutdown(vm:&mut synacor_vm::SynacorVM) -> ! {
+	let _ = vm.flush_output();
+	if let Some(path) = PROFILE_PATH.get() {
+		match profiler::write_report_file(vm, path) {
+			Ok(_) => println!("Wrote profile to {}", path),
+			Err(err) => println!("Error writing profile to {}: {}", path, err),
+		}
+	}
+	if let Some(path) = CALLGRAPH_PATH.get() {
+		match fs::write(path, vm.call_graph().to_dot()) {
+			Ok(_) => println!("Wrote call graph to {}", path),
+			Err(err) => println!("Error writing call graph to {}: {}", path, err),
+		}
+	}
+	match vm.stop_trace() {
+		Ok(0) => (),
+		Ok(records) => println!("Wrote {} trace records", records),
+		Err(err) => println!("Error writing trace: {}", err),
+	}
+	process::exit(0);
+}
+// a clean halt is the expected end of the game, only faults are worth reporting
+fn report_halt(reason:&synacor_vm::HaltReason) {
+	if let synacor_vm::HaltReason::Fault(_) = reason {
+		println!("{}", reason);
+	}
 }
 fn play_to_twisty_passages(vm:&mut synacor_vm::SynacorVM) {
 	println!("Taking tablet...");
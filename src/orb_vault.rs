@@ -1,3 +1,4 @@
+use super::synacor_vm::{SynacorVM, StopReason};
 
 #[derive(Clone)]
 struct OrbVaultMap {
@@ -9,15 +10,23 @@ struct OrbVaultMap {
 // return the node symbol 
 fn parse_vault_node(vm: &super::synacor_vm::SynacorVM, east:usize, north:usize) -> String {
 	let mut vm_clone = vm.clone();
+	let mut path:Vec<&str> = Vec::new();
 	for _y in 0..north {
-		vm_clone.input_line_string(format!("go north\n"));
+		path.push("go north");
 	}
 	for _x in 0..east {
-		vm_clone.input_line_string(format!("go east\n"));
+		path.push("go east");
+	}
+	for command in path {
+		if vm_clone.input_line_for(command, SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+			return "?".to_string();
+		}
 	}
 	// clear output
 	let _ = vm_clone.output_line(true);
-	vm_clone.input_line("look");
+	if vm_clone.input_line_for("look", SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+		return "?".to_string();
+	}
 	let look = vm_clone.output_line(true);
 	
 	// silly way to do this - split on single-quote
@@ -27,7 +36,8 @@ fn parse_vault_node(vm: &super::synacor_vm::SynacorVM, east:usize, north:usize)
 	}
 	return look_split[1].to_string();
 }
-fn map_vault(vm: &super::synacor_vm::SynacorVM) -> OrbVaultMap {
+// returns None if any room could not be explored
+fn map_vault(vm: &super::synacor_vm::SynacorVM) -> Option<OrbVaultMap> {
 	// determine the dimensions
 	let mut dim_clone = vm.clone();
 	let mut width = 1;
@@ -35,7 +45,9 @@ fn map_vault(vm: &super::synacor_vm::SynacorVM) -> OrbVaultMap {
 	// clear output
 	let _ = dim_clone.output_line(true);
 	loop {
-		dim_clone.input_line("go east");
+		if dim_clone.input_line_for("go east", SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+			return None;
+		}
 		let output = dim_clone.output_line(true);
 		if output.contains("I don't understand") {
 			break;
@@ -43,14 +55,16 @@ fn map_vault(vm: &super::synacor_vm::SynacorVM) -> OrbVaultMap {
 		width += 1;
 	}
 	loop {
-		dim_clone.input_line("go north");
+		if dim_clone.input_line_for("go north", SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+			return None;
+		}
 		let output = dim_clone.output_line(true);
 		if output.contains("I don't understand") {
 			break;
 		}
 		height += 1;
 	}
-	let mut map = OrbVaultMap { width:width, height:height, symbols:Vec::new(), values:Vec::new() };
+	let mut map = OrbVaultMap { width, height, symbols:Vec::new(), values:Vec::new() };
 	for y in 0..width {
 		for x in 0..height {
 			let symbol = parse_vault_node(vm, x,y);
@@ -58,18 +72,21 @@ fn map_vault(vm: &super::synacor_vm::SynacorVM) -> OrbVaultMap {
 		}
 	}
 	for i in 0..map.symbols.len() {
-		if map.symbols[i] == "*".to_string() || 
-			map.symbols[i] == "+".to_string() ||
-			map.symbols[i] == "-".to_string() {
+		if map.symbols[i] == "*" || 
+			map.symbols[i] == "+" ||
+			map.symbols[i] == "-" {
 				map.values.push(0);
 		}
 		else {
-			map.values.push(map.symbols[i].parse::<i32>().unwrap());
+			match map.symbols[i].parse::<i32>() {
+				Ok(val) => map.values.push(val),
+				Err(_) => return None,
+			}
 		}
 	}
-	return map;
+	return Some(map);
 }
-fn evaluate_route(map: &OrbVaultMap, route:&Vec<char> ) -> i32 {
+fn evaluate_route(map: &OrbVaultMap, route:&[char] ) -> i32 {
 	let mut val = map.values[0];
 	let mut x:isize = 0;
 	let mut y:isize = 0;
@@ -108,11 +125,11 @@ fn evaluate_route(map: &OrbVaultMap, route:&Vec<char> ) -> i32 {
 	}
 	return 0;
 }
-fn route_pos(route: &Vec<char>) -> (isize,isize) {
+fn route_pos(route: &[char]) -> (isize,isize) {
 	let mut x = 0;
 	let mut y = 0;
-	for i in 0..route.len() {
-		match route[i] {
+	for step in route.iter() {
+		match step {
 			'n' => y += 1,
 			'e' => x += 1,
 			's' => y -= 1,
@@ -124,7 +141,7 @@ fn route_pos(route: &Vec<char>) -> (isize,isize) {
 }
 // I think a bfs would be smarter, but using a dfs for progressivly larger maximum search depths 
 // is fine for this without too much repeated work.
-fn recurse(map: &OrbVaultMap, route: &Vec<char>, desired_result:i32, max_steps:usize) -> (bool,Vec<char>) {
+fn recurse(map: &OrbVaultMap, route: &[char], desired_result:i32, max_steps:usize) -> (bool,Vec<char>) {
 	let max_eval = 1<<16; // cutoff if we get stuck in a multiplication loop
 	if route.len() >= max_steps {
 		return (false,Vec::new());
@@ -167,7 +184,7 @@ fn recurse(map: &OrbVaultMap, route: &Vec<char>, desired_result:i32, max_steps:u
 		}
 		
 		for j in 1..=4 {
-			let mut new_route = route.clone();
+			let mut new_route = route.to_vec();
 			new_route.push(step1);
 			let (x2,y2) = route_pos(&new_route);
 			let step2:char;
@@ -227,28 +244,39 @@ fn recurse(map: &OrbVaultMap, route: &Vec<char>, desired_result:i32, max_steps:u
 	}
 	return (false,Vec::new());
 }
-fn try_route(vm:&mut super::synacor_vm::SynacorVM, route:&Vec<char>) {
-	vm.input_line("take orb");
+// returns false if the vm stopped short of the vault door
+fn try_route(vm:&mut super::synacor_vm::SynacorVM, route:&Vec<char>) -> bool {
+	let mut commands = vec!["take orb"];
 	for c in route {
 		match c {
-			'n' => vm.input_line("go north"),
-			'e' => vm.input_line("go east"),
-			's' => vm.input_line("go south"),
-			'w' => vm.input_line("go west"),
+			'n' => commands.push("go north"),
+			'e' => commands.push("go east"),
+			's' => commands.push("go south"),
+			'w' => commands.push("go west"),
 			_ => {
 					println!("Unknown direction {}", c); 
-					return; 
+					return false; 
 				}
 		}
 	}
-	vm.input_line("go vault");
+	commands.push("go vault");
+	return run_commands(vm, &commands);
+}
+// returns false if a command did not finish with the game asking for more input
+fn run_commands(vm:&mut super::synacor_vm::SynacorVM, commands:&[&str]) -> bool {
+	for command in commands {
+		if vm.input_line_for(command, SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+			println!("The game stopped responding after \"{}\".", command);
+			return false;
+		}
+	}
+	return true;
 }
-fn reverse(code:&String) -> String {
-	let code_chars:Vec<char> = code.chars().collect();
+fn reverse(code:&str) -> String {
 	let mut result_chars:Vec<char> = Vec::new();
 	let mut temp:char;
-	for i in 0..code_chars.len() {
-		temp = code_chars[i];
+	for c in code.chars() {
+		temp = c;
 		// possibly not an exhaustive list of reversal replacements
 		match temp {
 			'p' => temp = 'q',
@@ -261,18 +289,24 @@ fn reverse(code:&String) -> String {
 	}
 	return result_chars.into_iter().collect();
 }
-fn mirror_code(mirror_use:&String) -> String {
+fn mirror_code(mirror_use:&str) -> String {
 	// dumb way to extract the code string - split on double quote
 	let code_split:Vec<&str> = mirror_use.split(34 as char).collect();
 	if code_split.len() < 3 {
 		return "Code not found!".to_string();
 	}
-	return reverse(&code_split[1].to_string());
+	return reverse(code_split[1]);
 }
 // requires a vm placed at the orb position in the vault antechamber
 pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 	println!("Exploring the rooms around the vault...");
-	let map = map_vault(vm);
+	let map = match map_vault(vm) {
+		Some(map) => map,
+		None => {
+			println!("The rooms around the vault could not be fully explored.");
+			return false;
+		}
+	};
 	let desired_result = 1;
 	let steps_cutoff = 16;
 	println!("Pondering a route for the orb...");
@@ -283,12 +317,14 @@ pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 			continue;
 		}
 		println!("A route has been discovered! Taking the orb and proceeding to the vault...");
-		try_route(vm, &route);
-		vm.input_line("go vault");
-		vm.input_line("take mirror");
+		if !try_route(vm, &route) || !run_commands(vm, &["go vault", "take mirror"]) {
+			return false;
+		}
 		// clear output
 		let _ = vm.output_line(true);
-		vm.input_line("use mirror");
+		if !run_commands(vm, &["use mirror"]) {
+			return false;
+		}
 		let use_mirror = vm.output_line(true);
 		println!("{}", use_mirror);
 		println!("Thinking quickly, you realize that the writing on your forehead is reversed in the mirror! It must actually say \"{}\" ", mirror_code(&use_mirror));
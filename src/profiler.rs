@@ -0,0 +1,57 @@
+use super::synacor_vm::{InstructionAccumulator, SynacorVM, OPCODES};
+use super::interdimensional_physics::decompile_instruction;
+use std::fs;
+use std::io;
+
+fn percent(count:usize, total:usize) -> f64 {
+	if total == 0 {
+		return 0.0;
+	}
+	return count as f64 * 100.0 / total as f64;
+}
+fn disassemble(mem:&[u16], addr:u16) -> String {
+	if addr as usize >= mem.len() {
+		return String::new();
+	}
+	let (line, _) = decompile_instruction(mem, addr as usize);
+	return line;
+}
+
+// format a profile as text, hottest entries first, with each address
+// annotated with the instruction in mem
+pub fn report(profile:&InstructionAccumulator, mem:&[u16]) -> String {
+	let total = profile.total();
+	let mut out = format!("Instructions executed: {}\n", total);
+
+	out.push_str("\nFunctions\n");
+	out.push_str(&format!("{:>6} {:>8} {:>12} {:>7} {:>12} {:>7}  {}\n", "entry", "calls", "inclusive", "%", "exclusive", "%", "first instruction"));
+	for func in profile.function_counts() {
+		out.push_str(&format!("{:>6} {:>8} {:>12} {:>6.2}% {:>12} {:>6.2}%  {}\n",
+			func.entry, func.calls, func.inclusive, percent(func.inclusive, total), func.exclusive, percent(func.exclusive, total), disassemble(mem, func.entry)));
+	}
+
+	out.push_str("\nOpcodes\n");
+	for (opcode, count) in profile.opcode_counts() {
+		let name = match OPCODES.get(opcode as usize) {
+			Some((name, _)) => name.to_string(),
+			None => format!("DATA? {}", opcode),
+		};
+		out.push_str(&format!("{:<8} {:>12} {:>6.2}%\n", name, count, percent(count, total)));
+	}
+
+	out.push_str("\nAddresses\n");
+	for (addr, count) in profile.pc_counts() {
+		out.push_str(&format!("{:>12} {:>6.2}%  {}\n", count, percent(count, total), disassemble(mem, addr)));
+	}
+	return out;
+}
+
+// write the report for the vm's current profile, if it has one
+pub fn write_report_file(vm:&SynacorVM, path:&str) -> io::Result<bool> {
+	let profile = match vm.profile() {
+		Some(profile) => profile,
+		None => return Ok(false),
+	};
+	fs::write(path, report(profile, &vm.dump_mem()))?;
+	return Ok(true);
+}
@@ -1,3 +1,5 @@
+use super::synacor_vm::{SynacorVM, StopReason};
+
 struct Coin {
 	name: String,
 	val: u16,
@@ -32,8 +34,11 @@ fn solution() -> Vec<u16> {
 	}
 	return Vec::new();
 }
-fn examine_coin(vm:&mut super::synacor_vm::SynacorVM, coin_name:String) -> Coin {
-	vm.input_line_string(format!("look {}", coin_name));
+// returns None if the game stopped responding
+fn examine_coin(vm:&mut super::synacor_vm::SynacorVM, coin_name:String) -> Option<Coin> {
+	if vm.input_line_for(&format!("look {}", coin_name), SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+		return None;
+	}
 	let look = vm.output_line(true);
 	let mut val = 0;
 	if look.contains("two") {
@@ -51,23 +56,23 @@ fn examine_coin(vm:&mut super::synacor_vm::SynacorVM, coin_name:String) -> Coin
 	else if look.contains("nine") {
 		val = 9;
 	}
-	let coin = Coin{name: coin_name, val: val};
-	return coin;
+	let coin = Coin{name: coin_name, val};
+	return Some(coin);
 }
 fn parse_inventory(inventory:String) -> Vec<String> {
 	let lines:Vec<&str> = inventory.split(10 as char).collect();
 	let mut inv:Vec<String> = Vec::new();
 	let mut at_inv:bool = false;
-	for i in 0..lines.len() {
-		if lines[i].contains("Your inventory:") {
+	for line in lines.iter() {
+		if line.contains("Your inventory:") {
 			at_inv = true;
 			continue;
 		}
 		if at_inv {
-			if lines[i].len() < 2 {
+			if line.len() < 2 {
 				break;
 			}
-			inv.push(lines[i][2..].to_string());
+			inv.push(line[2..].to_string());
 		}
 	}
 	return inv;
@@ -78,13 +83,22 @@ pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 	println!("Pondering deeply...");
 	println!("Examining inventory...");
 	let _ = vm.output_line(true);
-	vm.input_line("inv");
+	if vm.input_line_for("inv", SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+		println!("The game stopped responding while checking the inventory.");
+		return false;
+	}
 	let inv = parse_inventory(vm.output_line(true));
 	
 	let mut coins:Vec<Coin> = Vec::new();
 	for item in inv {
 		if item.contains("coin") {
-			coins.push(examine_coin(vm, item));
+			match examine_coin(vm, item) {
+				Some(coin) => coins.push(coin),
+				None => {
+					println!("The game stopped responding while examining the coins.");
+					return false;
+				}
+			}
 		}
 	}
 	let solution = solution();
@@ -93,11 +107,14 @@ pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 		return false;
 	}
 	for val in solution {
-		for i in 0..coins.len() {
-			if coins[i].val == val {
+		for coin in coins.iter() {
+			if coin.val == val {
 				let _ = vm.output_line(true);
-				println!("Using the {}...", coins[i].name);
-				vm.input_line_string(format!("use {}", coins[i].name));
+				println!("Using the {}...", coin.name);
+				if vm.input_line_for(&format!("use {}", coin.name), SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+					println!("The game stopped responding after using the {}.", coin.name);
+					return false;
+				}
 				break
 			}
 		}
@@ -0,0 +1,112 @@
+// User names and comments for addresses, kept in a side file and shown by the
+// decompiler and the debugger. One address per line, decimal or 0x prefixed,
+// followed by an optional name and an optional comment after a semicolon:
+//
+//     2733 current_room_id ; id of the room the player is in
+//     6027 teleporter_confirm
+//     5489 ; just a comment
+//
+// Lines starting with # are ignored. Names follow the assembler's label
+// syntax so a listing that uses them still assembles.
+use super::assembler::is_label;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(Debug)]
+pub enum SymbolError {
+	Io(io::Error),
+	// 1 based line in the symbol file
+	Parse { line:usize, message:String },
+}
+
+impl fmt::Display for SymbolError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SymbolError::Io(err) => write!(f, "I/O error: {}", err),
+			SymbolError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+		}
+	}
+}
+
+impl From<io::Error> for SymbolError {
+	fn from(err: io::Error) -> SymbolError {
+		return SymbolError::Io(err);
+	}
+}
+
+fn parse_addr(text:&str) -> Option<u16> {
+	if let Some(hex) = text.strip_prefix("0x") {
+		return u16::from_str_radix(hex, 16).ok();
+	}
+	return text.parse::<u16>().ok();
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+	names: HashMap<u16, String>,
+	comments: HashMap<u16, String>,
+}
+
+impl Symbols {
+	pub fn parse(text:&str) -> Result<Symbols, SymbolError> {
+		let mut symbols = Symbols::default();
+		for (index, line) in text.lines().enumerate() {
+			let line_number = index + 1;
+			let error = |message:String| SymbolError::Parse { line: line_number, message };
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let (fields, comment) = match line.split_once(';') {
+				Some((fields, comment)) => (fields, Some(comment.trim())),
+				None => (line, None),
+			};
+			let fields:Vec<&str> = fields.split_whitespace().collect();
+			let addr = match fields.first().and_then(|field| parse_addr(field)) {
+				Some(addr) => addr,
+				None => return Err(error(format!("expected an address, found {}", fields.first().unwrap_or(&"nothing")))),
+			};
+			match fields.len() {
+				1 => (),
+				2 if is_label(fields[1]) => {
+					if symbols.names.values().any(|name| name == fields[1]) {
+						return Err(error(format!("duplicate name {}", fields[1])));
+					}
+					symbols.names.insert(addr, fields[1].to_string());
+				},
+				2 => return Err(error(format!("invalid name {}", fields[1]))),
+				_ => return Err(error("put comments after a ;".to_string())),
+			}
+			if let Some(comment) = comment {
+				if !comment.is_empty() {
+					symbols.comments.insert(addr, comment.to_string());
+				}
+			}
+		}
+		return Ok(symbols);
+	}
+	pub fn read(path:&str) -> Result<Symbols, SymbolError> {
+		return Symbols::parse(&fs::read_to_string(path)?);
+	}
+	pub fn name(&self, addr:u16) -> Option<&String> {
+		return self.names.get(&addr);
+	}
+	pub fn comment(&self, addr:u16) -> Option<&String> {
+		return self.comments.get(&addr);
+	}
+	pub fn names(&self) -> &HashMap<u16, String> {
+		return &self.names;
+	}
+	pub fn comments(&self) -> &HashMap<u16, String> {
+		return &self.comments;
+	}
+	// the name of addr followed by the address, or just the address
+	pub fn describe(&self, addr:u16) -> String {
+		return match self.names.get(&addr) {
+			Some(name) => format!("{} ({})", name, addr),
+			None => addr.to_string(),
+		};
+	}
+}
@@ -1,99 +1,201 @@
+mod accumulator;
+mod breakpoints;
+mod callstack;
+mod console;
+mod diff;
+mod events;
+mod handle;
+mod hooks;
+mod journal;
+mod memory;
+mod save_state;
+#[cfg(test)]
+mod tests;
+mod trace;
+
 use std::collections::HashMap; 
+use std::fmt;
 use std::io;
+use std::io::Write;
+use hooks::Hook;
+use events::Observers;
+use journal::Journal;
+use memory::{Decoded, Memory};
+use trace::{Tracer, TracePre};
+pub use accumulator::{InstructionAccumulator, FunctionProfile};
+pub use breakpoints::{Breakpoints, StopReason};
+pub use callstack::{CallFrame, CallGraph};
+pub use hooks::{HookAction, VmState};
+pub use diff::VmDiff;
+pub use events::{Event, Observer};
+pub use handle::{VmHandle, VmCommand, VmEvent};
+pub use console::{Input, Output, StdinInput, StdoutOutput, BufferInput, BufferOutput, FileInput, FileOutput, ChannelInput, ChannelOutput};
+pub use save_state::{SaveStateError, SAVE_STATE_VERSION};
+pub use trace::{TraceConfig, TraceFormat, TRACE_VERSION};
 
 const MEM_MAX: u16 = 32767;
 const LITERAL_MAX: u16 = 32767;
 const REG_ADDR_MAX: u16 = 32775;
 const REG_N: usize = 8;
 
-type VMResult<T> = Result<T, String>;
+pub type VMResult<T> = Result<T, VMError>;
+
+// mnemonic and operand count of each opcode, indexed by opcode
+pub const OPCODES: [(&str, usize); 22] = [
+	("HALT", 0), ("SET", 2), ("PUSH", 1), ("POP", 1), ("EQ", 3), ("GT", 3), ("JMP", 1), ("JT", 2),
+	("JF", 2), ("ADD", 3), ("MULT", 3), ("MOD", 3), ("AND", 3), ("OR", 3), ("NOT", 2), ("RMEM", 2),
+	("WMEM", 2), ("CALL", 1), ("RET", 0), ("OUT", 1), ("IN", 1), ("NOOP", 0),
+];
 
-// histogram of memory positions for executed instructions
-// designed for getting started with teleporter analysis
-pub struct InstructionAccumulator {
-	acc:HashMap<u16, usize>,
+// How closely the vm holds programs to the architecture spec.
+//
+// Strict traps on every spec violation: POP on an empty stack and characters
+// outside 7-bit ASCII for OUT and IN.
+//
+// Lenient is what the challenge binary needs. POP on an empty stack halts
+// cleanly, OUT truncates values to 8 bits and IN masks characters to 15 bits.
+//
+// In both modes a literal where a register is required, operands above 32775,
+// undefined opcodes and MOD by zero are faults, and RET on an empty stack
+// halts cleanly as the spec requires.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+	Strict,
+	#[default]
+	Lenient,
 }
 
-impl InstructionAccumulator {
-	pub fn new() -> InstructionAccumulator {
-		InstructionAccumulator { acc: HashMap::new()}
-	}
-	pub fn clear(&mut self) {
-		self.acc.clear();
-	}
-	pub fn record(&mut self, pos:u16) {
-		*self.acc.entry(pos).or_insert(0) += 1;
+// per-instance state, such as an open trace file or undo journal, that a clone starts without
+struct Detached<T>(Option<T>);
+
+impl<T> Clone for Detached<T> {
+	fn clone(&self) -> Detached<T> {
+		return Detached(None);
 	}
-	pub fn print(&self) {
-		println!("Distinct memory positions: {}", self.acc.keys().len());
-		let mut key_vec:Vec<u16> = Vec::new();
-		for k in self.acc.keys() {
-			key_vec.push(*k);
+}
+
+// faults raised while executing an instruction. addr is the address of the
+// faulting instruction and opcode the instruction being executed there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VMError {
+	// operand is outside the literal and register ranges, or is not a register where one is required
+	InvalidOperand { addr:u16, opcode:u16, operand:u16 },
+	StackUnderflow { addr:u16, opcode:u16 },
+	UndefinedOpcode { addr:u16, opcode:u16 },
+	// target is the address that could not be read or written
+	MemoryOutOfRange { addr:u16, opcode:u16, target:u16 },
+	DivisionByZero { addr:u16, opcode:u16 },
+	// the program counter left the address space, so there is no opcode to report
+	InvalidProgramCounter { addr:u16 },
+	// OUT of a value, or IN of a character, outside 7-bit ASCII. Character codes
+	// above 65535 are reported as 65535.
+	NonAscii { addr:u16, opcode:u16, val:u16 },
+}
+
+impl fmt::Display for VMError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			VMError::InvalidOperand { addr, opcode, operand } => write!(f, "Invalid operand {} for opcode {} at address {}", operand, opcode, addr),
+			VMError::StackUnderflow { addr, opcode } => write!(f, "Empty stack for opcode {} at address {}", opcode, addr),
+			VMError::UndefinedOpcode { addr, opcode } => write!(f, "Undefined opcode {} at address {}", opcode, addr),
+			VMError::MemoryOutOfRange { addr, opcode, target } => write!(f, "Memory access at address {} out of range for opcode {} at address {}", target, opcode, addr),
+			VMError::DivisionByZero { addr, opcode } => write!(f, "Division by zero for opcode {} at address {}", opcode, addr),
+			VMError::InvalidProgramCounter { addr } => write!(f, "Program counter {} out of range", addr),
+			VMError::NonAscii { addr, opcode, val } => write!(f, "Non-ASCII character {} for opcode {} at address {}", val, opcode, addr),
 		}
-		key_vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
-		let mut sum = 0;
-		for k in key_vec {
-			println!("{}:\t{}", k, self.acc[&k]);
-			sum += self.acc[&k];
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HaltReason {
+	CleanHalt { addr:u16, opcode:u16 },
+	Fault(VMError),
+}
+
+impl fmt::Display for HaltReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			HaltReason::CleanHalt { addr, opcode } => write!(f, "Halted successfully by opcode {} at address {}", opcode, addr),
+			HaltReason::Fault(err) => write!(f, "Halted with error: {}", err),
 		}
-		println!("Sum of positions: {}", sum);	
 	}
 }
 
 #[derive(Clone)]
 pub struct SynacorVM {
-	mem: HashMap<u16,u16>,
+	mem: Memory,
+	// the instruction being executed
+	inst: Decoded,
 	reg: [u16; REG_N],
 	stack: Vec<u16>,
 	mem_ptr: u16,
-	halt: bool,
-	halt_code: String,
-	halt_err: bool,
+	halt: Option<HaltReason>,
+	// instructions completed since the vm was created
+	executed: u64,
 	interactive: bool,
-	output_buff: Vec<char>,
-	output_buff_index: usize,
+	strictness: Strictness,
+	input: Box<dyn Input>,
+	output: Box<dyn Output>,
+	// line of input being consumed by IN
 	input_buff: Vec<char>,
 	input_buff_index: usize,
 	awaiting_input:bool,
 	input_ready:bool,
+	breakpoints: Breakpoints,
+	// watchpoint hit by the instruction being executed
+	watch_hit: Option<StopReason>,
+	// breakpoint execute() last stopped at, so resuming does not stop there again
+	break_resume: Option<u16>,
+	hooks: HashMap<u16, Hook>,
+	// guest calls in progress, outermost first
+	frames: Vec<CallFrame>,
+	call_graph: CallGraph,
+	tracer: Detached<Tracer>,
+	journal: Detached<Journal>,
+	profiler: Detached<InstructionAccumulator>,
+	observers: Detached<Observers>,
 }
 impl SynacorVM {
+	// interactive mode reads stdin and prints to stdout. Otherwise input is
+	// supplied with set_input_line() and output collected with output_line().
 	pub fn new(interactive:bool) -> SynacorVM {
-		SynacorVM { mem: HashMap::new(), reg:[0;REG_N], stack: Vec::new(), mem_ptr: 0, halt: false, halt_code:String::new(), halt_err: false, interactive: interactive, input_buff:Vec::new(), input_buff_index:0, output_buff:Vec::new(), output_buff_index: 0, awaiting_input:false, input_ready:false}
+		let mut vm = SynacorVM::with_io(Box::new(BufferInput::new()), Box::new(BufferOutput::new()));
+		if interactive {
+			vm.set_interactive(true);
+		}
+		return vm;
+	}
+	pub fn with_io(input:Box<dyn Input>, output:Box<dyn Output>) -> SynacorVM {
+		SynacorVM {
+			mem: Memory::new(), inst: Decoded::default(), reg:[0;REG_N], stack: Vec::new(), mem_ptr: 0, halt: None, executed: 0, interactive: false, strictness: Strictness::default(), input, output,
+			input_buff:Vec::new(), input_buff_index:0, awaiting_input:false, input_ready:false,
+			breakpoints: Breakpoints::default(), watch_hit: None, break_resume: None, hooks: HashMap::new(), frames: Vec::new(), call_graph: CallGraph::default(), tracer: Detached(None), journal: Detached(None), profiler: Detached(None), observers: Detached(None),
+		}
 	}
 	// set the input buffer and continue execution
 	pub fn input_line_string(&mut self, input: String) {
 		if !self.awaiting_input {
 			return;
 		}
-		self.input_buff.clear();
-		let input_chars:Vec<char> = input.chars().collect();
-		for c in input_chars {
-			self.input_buff.push(c);
-		}
-		if !input.contains("\n") {
-			self.input_buff.push('\n');
-		}
-		self.input_buff_index = self.input_buff.len();
-		self.input_ready = true;
-		self.awaiting_input = false;
+		self.set_input_line(&input);
 		self.execute();
 	}
 	pub fn input_line(&mut self, input:&str) {
 		let mut s:String = input.to_string();
 		if !s.contains("\n") {
-			s.push_str("\n");
+			s.push('\n');
 		}
 		self.input_line_string(s);
 	}
 	pub fn set_input_line(&mut self, input:&str) {
-		let mut s:String = input.to_string();
-		if !s.contains("\n") {
-			s.push_str("\n");
-		}
 		if !self.awaiting_input {
 			return;
 		}
+		self.load_input_line(input);
+	}
+	// replace the line being read by IN
+	fn load_input_line(&mut self, input:&str) {
+		self.journal_command();
 		self.input_buff.clear();
 		let input_chars:Vec<char> = input.chars().collect();
 		for c in input_chars {
@@ -103,23 +205,67 @@ impl SynacorVM {
 			self.input_buff.push('\n');
 		}
 		self.input_buff_index = self.input_buff.len();
+		self.emit(Event::InputConsumed(input.trim_end_matches('\n').to_string()));
 		self.input_ready = true;
 		self.awaiting_input = false;
 	}
+	// fetch the next line from the input backend. Returns false if it has none.
+	fn fetch_input(&mut self) -> bool {
+		// a prompt written to the output should be visible before blocking for input
+		let _ = self.output.flush();
+		return match self.input.read_line() {
+			Some(line) => {
+				self.load_input_line(&line);
+				true
+			},
+			None => false,
+		};
+	}
+	// output held by the output backend. Empty for backends that do not keep their output.
 	pub fn output_line(&mut self, reset:bool) -> String {
-		let s: String = self.output_buff.iter().collect();
+		let buffer = match self.output.buffer_mut() {
+			Some(buffer) => buffer,
+			None => return String::new(),
+		};
+		let s: String = buffer.iter().collect();
 		if reset {
-			self.output_buff.clear();
-			self.output_buff_index = 0;
+			buffer.clear();
 		}
 		return s;
 	}
+	// switch between stdin/stdout and the in-memory buffers. Buffered output is
+	// discarded when the mode changes.
 	pub fn set_interactive(&mut self, interactive:bool) {
+		if interactive == self.interactive {
+			return;
+		}
 		self.interactive = interactive;
 		if self.interactive {
+			self.input = Box::new(StdinInput);
+			self.output = Box::new(StdoutOutput);
 			self.awaiting_input = false;
 			self.input_ready = false;
 		}
+		else {
+			self.input = Box::new(BufferInput::new());
+			self.output = Box::new(BufferOutput::new());
+		}
+	}
+	pub fn set_strictness(&mut self, strictness:Strictness) {
+		self.strictness = strictness;
+	}
+	pub fn strictness(&self) -> Strictness {
+		return self.strictness;
+	}
+	pub fn set_input(&mut self, input:Box<dyn Input>) {
+		self.input = input;
+	}
+	pub fn set_output(&mut self, output:Box<dyn Output>) {
+		self.output = output;
+	}
+	// flush the output backend, reporting the first error it encountered
+	pub fn flush_output(&mut self) -> io::Result<()> {
+		return self.output.flush();
 	}
 	pub fn set_register(&mut self, index:usize, val:u16) {
 		if index >= REG_N {
@@ -127,68 +273,287 @@ impl SynacorVM {
 		}
 		self.reg[index] = val;
 	}
-	pub fn get_register(&mut self, index:usize) -> u16 {
+	pub fn get_register(&self, index:usize) -> u16 {
 		if index >= REG_N {
 			return 0;
 		}
 		return self.reg[index];
 	}
-	pub fn get_mem_ptr(&mut self) -> u16 {
+	pub fn get_mem_ptr(&self) -> u16 {
 		return self.mem_ptr;
 	}
 	pub fn set_mem_ptr(&mut self, ptr:u16) {
 		self.mem_ptr = ptr;
 	}
-	pub fn load_mem(&mut self, input:Vec<u16>) -> VMResult<bool> {	
+	pub fn load_mem(&mut self, input:Vec<u16>) -> Result<bool, String> {	
 		if input.len() >= MEM_MAX as usize {
 			return Err(format!("LOAD MEM: input length {} exceeds address space capacity {}", input.len(), MEM_MAX + 1))
 		}
-		self.mem.clear();
-		for i in 0..input.len() {
-			self.mem.insert(i as u16, input[i]);
-		}
+		self.mem.load(&input);
+		self.clear_frames();
+		self.clear_journal();
 		return Ok(true);
 	}
-	pub fn get_mem(&mut self, addr:u16) -> VMResult<u16> {
-		return self.mem_read(addr);
+	pub fn get_mem(&self, addr:u16) -> Option<u16> {
+		if addr > MEM_MAX {
+			return None;
+		}
+		return Some(self.mem.read(addr));
+	}
+	pub fn set_mem(&mut self, addr:u16, val:u16) -> bool {
+		if addr > MEM_MAX {
+			return false;
+		}
+		self.mem.write(addr, val);
+		return true;
+	}
+	// copy of the whole address space, indexed by address
+	pub fn dump_mem(&self) -> Vec<u16> {
+		return (0..=MEM_MAX).map(|addr| self.mem.read(addr)).collect();
 	}
-	pub fn is_halted(&mut self) -> bool {
-		return self.halt;
+	// stack contents, bottom first
+	pub fn get_stack(&self) -> &[u16] {
+		return &self.stack;
 	}
-	pub fn is_awaiting_input(&mut self) -> bool {
+	pub fn is_halted(&self) -> bool {
+		return self.halt.is_some();
+	}
+	// cache decoded instructions between executions. On by default. While no
+	// breakpoint, hook, trace, journal, profiler or observer is attached,
+	// execute() and execute_for() run cached instructions without step().
+	pub fn set_decode_cache(&mut self, enabled:bool) {
+		self.mem.set_decode_cache(enabled);
+	}
+	pub fn decode_cache(&self) -> bool {
+		return self.mem.decode_cache();
+	}
+	pub fn instruction_count(&self) -> u64 {
+		return self.executed;
+	}
+	pub fn halt_reason(&self) -> Option<&HaltReason> {
+		return self.halt.as_ref();
+	}
+	pub fn is_awaiting_input(&self) -> bool {
 		return self.awaiting_input;
 	}
-	pub fn execute(&mut self) {
-		while !self.halt && (!self.awaiting_input) {
-			self.execute_one();
+	// record every executed instruction to out. Replaces any trace already running.
+	// Clones of the vm are not traced.
+	pub fn start_trace(&mut self, out:Box<dyn Write + Send>, config:TraceConfig) -> io::Result<()> {
+		let _ = self.stop_trace();
+		self.tracer = Detached(Some(Tracer::new(out, config)?));
+		return Ok(());
+	}
+	pub fn start_trace_file(&mut self, path:&str, config:TraceConfig) -> io::Result<()> {
+		let out = trace::open_file(path)?;
+		return self.start_trace(out, config);
+	}
+	// stop tracing and flush the output. Returns the number of records written.
+	pub fn stop_trace(&mut self) -> io::Result<u64> {
+		return match self.tracer.0.take() {
+			Some(tracer) => tracer.finish(),
+			None => Ok(0),
+		};
+	}
+	pub fn breakpoints(&self) -> &Breakpoints {
+		return &self.breakpoints;
+	}
+	pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+		return &mut self.breakpoints;
+	}
+	// run until the program halts, waits for input or hits a breakpoint or watchpoint
+	pub fn execute(&mut self) -> StopReason {
+		loop {
+			if self.unwatched() {
+				self.execute_checked(u64::MAX);
+			}
+			if let Some(stop) = self.step() {
+				return stop;
+			}
 		}
 	}
-	pub fn execute_once(&mut self) {
-		if self.halt {
-			println!("EXECUTE_ONCE: HALTED");
-			return;
+	// execute a single instruction, checking breakpoints and watchpoints.
+	// Returns the reason to stop, if any.
+	fn step(&mut self) -> Option<StopReason> {
+		if let Some(reason) = &self.halt {
+			return Some(StopReason::Halted(reason.clone()));
 		}
-		if self.awaiting_input && !self.input_ready {
-			println!("EXECUTE_ONCE: AWAITING INPUT");
-			return;
+		if self.awaiting_input && !self.input_ready && !self.fetch_input() {
+			return Some(StopReason::AwaitingInput);
+		}
+		let resume = self.break_resume.take();
+		if self.breakpoints.has_pc(self.mem_ptr) && resume != Some(self.mem_ptr) {
+			self.break_resume = Some(self.mem_ptr);
+			return Some(StopReason::Breakpoint { addr: self.mem_ptr });
+		}
+		if !self.hooks.is_empty() && self.run_hook() {
+			self.observe_halt();
+			return None;
 		}
 		self.execute_one();
-	}
-	fn execute_one(&mut self) {
-		if self.halt {
-			println!("{}", self.halt_code);
-			return;
+		self.observe_halt();
+		return self.watch_hit.take();
+	}
+	// the instruction or hook just run halted the vm
+	fn observe_halt(&mut self) {
+		if let Some(reason) = self.halt.clone() {
+			self.emit(Event::Halted(reason));
+		}
+	}
+	// run at most max_instructions instructions
+	pub fn execute_for(&mut self, max_instructions:u64) -> StopReason {
+		let mut steps = 0;
+		while steps < max_instructions {
+			if self.unwatched() {
+				steps += self.execute_checked(max_instructions - steps);
+				if steps == max_instructions {
+					break;
+				}
+			}
+			if let Some(stop) = self.step() {
+				return stop;
+			}
+			steps += 1;
+		}
+		return StopReason::BudgetExhausted;
+	}
+	// nothing needs to see the instructions execute_checked() would run
+	fn unwatched(&self) -> bool {
+		return self.mem.decode_cache() && self.halt.is_none() && !self.awaiting_input && self.break_resume.is_none() && self.breakpoints.is_empty() && self.hooks.is_empty()
+			&& self.tracer.0.is_none() && self.journal.0.is_none() && self.profiler.0.is_none() && self.observers.0.is_none();
+	}
+	// run cached instructions whose operands were checked when they were decoded,
+	// without the per-instruction checks in step(). Stops at the first instruction
+	// that needs them, and returns the number run.
+	fn execute_checked(&mut self, max_instructions:u64) -> u64 {
+		// a checked register operand is in 32768..=32775
+		fn reg(arg:u16) -> usize {
+			return (arg - LITERAL_MAX - 1) as usize & (REG_N - 1);
+		}
+		let mut count = 0;
+		while count < max_instructions && self.mem_ptr <= MEM_MAX {
+			let inst = self.mem.decode(self.mem_ptr);
+			if !inst.checked {
+				break;
+			}
+			let [a, b, c] = inst.args;
+			let val = |arg:u16| if arg <= LITERAL_MAX { arg } else { self.reg[reg(arg)] };
+			match inst.opcode {
+				1 => {
+					self.reg[reg(a)] = val(b);
+					self.mem_ptr += 3;
+				},
+				2 => {
+					self.stack.push(val(a));
+					self.mem_ptr += 2;
+				},
+				3 => match self.stack.pop() {
+					Some(x) => {
+						self.reg[reg(a)] = x;
+						self.mem_ptr += 2;
+					},
+					// underflow is handled by op_pop()
+					None => break,
+				},
+				6 => self.mem_ptr = val(a),
+				7 | 8 => {
+					if (val(a) != 0) == (inst.opcode == 7) {
+						self.mem_ptr = val(b);
+					}
+					else {
+						self.mem_ptr += 3;
+					}
+				},
+				11 if val(c) == 0 => break,
+				4 | 5 | 9..=13 => {
+					let (x, y) = (val(b), val(c));
+					self.reg[reg(a)] = match inst.opcode {
+						4 => (x == y) as u16,
+						5 => (x > y) as u16,
+						9 => ((x as u32 + y as u32) % (LITERAL_MAX as u32 + 1)) as u16,
+						10 => ((x as u32 * y as u32) % (LITERAL_MAX as u32 + 1)) as u16,
+						11 => x % y,
+						12 => x & y,
+						_ => x | y,
+					};
+					self.mem_ptr += 4;
+				},
+				14 => {
+					self.reg[reg(a)] = (!val(b)) & LITERAL_MAX;
+					self.mem_ptr += 3;
+				},
+				// addresses out of range are faulted by op_rmem() and op_wmem()
+				15 if val(b) > MEM_MAX => break,
+				15 => {
+					self.reg[reg(a)] = self.mem.read(val(b));
+					self.mem_ptr += 3;
+				},
+				16 if val(a) > MEM_MAX => break,
+				16 => {
+					let (addr, x) = (val(a), val(b));
+					self.mem.write(addr, x);
+					self.mem_ptr += 3;
+				},
+				17 => {
+					let (call_site, entry) = (self.mem_ptr, val(a));
+					self.stack.push(call_site + 2);
+					self.mem_ptr = entry;
+					self.enter_frame(call_site, entry);
+				},
+				// an empty stack halts in op_ret()
+				18 if self.stack.is_empty() => break,
+				18 => {
+					self.leave_frame();
+					self.mem_ptr = self.stack.pop().unwrap();
+				},
+				// non-ASCII output is checked by op_out()
+				19 if val(a) > 127 && self.strictness == Strictness::Strict => break,
+				19 => {
+					self.output.write_char((val(a) as u8) as char);
+					self.mem_ptr += 2;
+				},
+				_ => self.mem_ptr += 1,
+			}
+			count += 1;
 		}
-		if self.awaiting_input && !self.input_ready {
-			return;
+		self.executed += count;
+		return count;
+	}
+	// run until predicate returns true, checked before each instruction
+	pub fn run_until<F: FnMut(&SynacorVM) -> bool>(&mut self, mut predicate:F) -> StopReason {
+		loop {
+			if predicate(self) {
+				return StopReason::Condition;
+			}
+			if let Some(stop) = self.step() {
+				return stop;
+			}
 		}
-		let opcode_result = self.mem_read(self.mem_ptr);
-		if !opcode_result.is_ok() {
-			self.set_halt_with_error(format!("Exception while reading opcode at index {} - {}", self.mem_ptr, opcode_result.unwrap_err()));
+	}
+	// instructions a single game command may take before we give up on it
+	pub const COMMAND_BUDGET: u64 = 10000000;
+	// supply a line of game input and run for at most max_instructions
+	pub fn input_line_for(&mut self, input:&str, max_instructions:u64) -> StopReason {
+		self.set_input_line(input);
+		return self.execute_for(max_instructions);
+	}
+	// execute one instruction. Returns the reason to stop, if any, including
+	// why nothing ran when the vm is halted or waiting for input
+	pub fn execute_once(&mut self) -> Option<StopReason> {
+		return self.step();
+	}
+	// step() has already checked that the vm is not halted or waiting for input
+	fn execute_one(&mut self) {
+		self.journal_begin();
+		if self.mem_ptr > MEM_MAX {
+			self.halt = Some(HaltReason::Fault(VMError::InvalidProgramCounter { addr: self.mem_ptr }));
+			self.journal_end();
 			return;
 		}
-		let opcode = opcode_result.unwrap();
-		match opcode {
+		let pc = self.mem_ptr;
+		self.inst = self.mem.decode(pc);
+		let opcode = self.inst.opcode;
+		let trace_pre = self.trace_pre(opcode);
+		let result = match opcode {
 			0 => self.op_halt(),
 			1 => self.op_set(),
 			2 => self.op_push(),
@@ -212,809 +577,298 @@ impl SynacorVM {
 			20 => self.op_in(),
 			21 => self.op_noop(),
 			_ => self.op_undefined(opcode),
+		};
+		if let Err(err) = result {
+			self.halt = Some(HaltReason::Fault(err));
 		}
-	}
-	fn val(&mut self, val:u16) -> VMResult<u16> {
-			if val <= LITERAL_MAX {
-				return Ok(val);
-			}
-			else if val > LITERAL_MAX && val <= REG_ADDR_MAX {
-				return Ok(self.reg[(val - LITERAL_MAX - 1) as usize]);
-			}
-			else {
-				self.set_halt_with_error(format!("VAL: Invalid value {}", val));
-				return Err(format!("VAL: Invalid value {}", val));
+		self.journal_end();
+		if !self.awaiting_input {
+			self.executed += 1;
+		}
+		self.profile_instruction(pc, opcode);
+		if let Some(tracer) = self.tracer.0.as_mut() {
+			tracer.end_instruction(trace_pre, &self.reg, !self.awaiting_input);
+		}
+	}
+	// capture the instruction and operand values for the trace, if this instruction is traced
+	fn trace_pre(&mut self, opcode:u16) -> Option<TracePre> {
+		let seq = self.tracer.0.as_mut()?.wants(self.mem_ptr)?;
+		let arg_count = OPCODES.get(opcode as usize).map(|op| op.1).unwrap_or(0);
+		let mut args:Vec<(u16, u16)> = Vec::new();
+		for n in 1..=arg_count as u16 {
+			let addr = self.mem_ptr + n;
+			if addr > MEM_MAX {
+				break;
 			}
+			let raw = self.mem.read(addr);
+			args.push((raw, self.val(raw).unwrap_or(raw)));
+		}
+		return Some(Tracer::pre(seq, self.mem_ptr, opcode, args, self.reg));
+	}
+	// opcode of the instruction currently being executed, for error reporting
+	fn opcode(&self) -> u16 {
+		return self.mem.read(self.mem_ptr);
 	}
+	fn invalid_operand(&self, operand:u16) -> VMError {
+		return VMError::InvalidOperand { addr: self.mem_ptr, opcode: self.opcode(), operand };
+	}
+	fn stack_underflow(&self) -> VMError {
+		return VMError::StackUnderflow { addr: self.mem_ptr, opcode: self.opcode() };
+	}
+	fn val(&self, val:u16) -> VMResult<u16> {
+		if val <= LITERAL_MAX {
+			return Ok(val);
+		}
+		else if val > LITERAL_MAX && val <= REG_ADDR_MAX {
+			return Ok(self.reg[(val - LITERAL_MAX - 1) as usize]);
+		}
+		else {
+			return Err(self.invalid_operand(val));
+		}
+	}
+	// data read on behalf of an instruction
 	fn mem_read(&mut self, addr:u16) -> VMResult<u16> {
 		if addr > MEM_MAX {
-			self.set_halt_with_error(format!("Invalid memory read access at address {} (out of range)", addr));
-			return Err(format!("Invalid memory write access at address {} (out of range)", addr));
+			return Err(VMError::MemoryOutOfRange { addr: self.mem_ptr, opcode: self.opcode(), target: addr });
 		}
-		if !self.mem.contains_key(&addr) {
-			return Ok(0);
+		let val = self.mem.read(addr);
+		if self.breakpoints.has_read(addr) {
+			self.watch_hit = Some(StopReason::MemoryRead { pc: self.mem_ptr, addr, val });
 		}
-		return Ok(self.mem[&addr]);
+		return Ok(val);
 	}
-	fn mem_write(&mut self, addr:u16, val:u16) -> VMResult<bool> {
+	fn mem_write(&mut self, addr:u16, val:u16) -> VMResult<()> {
 		if addr > MEM_MAX {
-			self.set_halt_with_error(format!("Invalid memory write access at address {} (out of range)", addr));
-			return Err(format!("Invalid memory write access at address {} (out of range)", addr));
+			return Err(VMError::MemoryOutOfRange { addr: self.mem_ptr, opcode: self.opcode(), target: addr });
 		}
-		self.mem.insert(addr, val);
-		return Ok(true);
+		if self.breakpoints.has_write(addr) {
+			self.watch_hit = Some(StopReason::MemoryWrite { pc: self.mem_ptr, addr, old: self.mem.read(addr), new: val });
+		}
+		if let Some(tracer) = self.tracer.0.as_mut() {
+			tracer.note_write(addr, self.mem.read(addr), val);
+		}
+		if let Some(journal) = self.journal.0.as_mut() {
+			journal.note_write(addr, self.mem.read(addr));
+		}
+		self.emit(Event::MemoryWrite { pc: self.mem_ptr, addr, old: self.mem.read(addr), new: val });
+		self.mem.write(addr, val);
+		return Ok(());
 	}
-	fn is_reg_addr(&mut self, addr: u16) -> bool {
-		return addr > LITERAL_MAX && addr <= REG_ADDR_MAX;
+	fn set_reg(&mut self, reg:usize, val:u16) {
+		if self.breakpoints.has_register(reg) {
+			self.watch_hit = Some(StopReason::RegisterWrite { pc: self.mem_ptr, reg, old: self.reg[reg], new: val });
+		}
+		self.reg[reg] = val;
 	}
-	fn reg_index(&mut self, addr: u16) -> VMResult<usize> {
-		if addr > LITERAL_MAX && addr <= REG_ADDR_MAX {
-			return Ok((addr - LITERAL_MAX - 1) as usize);
+	fn stack_pop(&mut self) -> Option<u16> {
+		let val = self.stack.pop();
+		if let (Some(v), Some(journal)) = (val, self.journal.0.as_mut()) {
+			journal.note_pop(v);
 		}
-		return Err(format!("Register address {} ({}) out of range", addr, addr - LITERAL_MAX - 1));
+		return val;
 	}
-	fn reg_set(&mut self, reg_addr: u16, val: u16) -> VMResult<bool> {
-		let reg_index = self.reg_index(reg_addr);
-		if reg_index.is_ok() {
-			self.reg[reg_index.unwrap()] = val;
-			return Ok(true);
+	// raw operand n (1-based) of the current instruction. Operand fetches do not trigger watchpoints.
+	fn arg(&self, n:u16) -> VMResult<u16> {
+		let addr = self.mem_ptr + n;
+		if addr > MEM_MAX {
+			return Err(VMError::MemoryOutOfRange { addr: self.mem_ptr, opcode: self.opcode(), target: addr });
 		}
-		else {
-			return Err(format!("REG SET: Register index {} is out of range", reg_addr));
+		return Ok(self.inst.args[n as usize - 1]);
+	}
+	// operand n interpreted as a literal or register value
+	fn arg_val(&self, n:u16) -> VMResult<u16> {
+		let arg = self.arg(n)?;
+		return self.val(arg);
+	}
+	// the register a destination operand names. A literal is a fault in both modes.
+	fn arg_reg(&self, n:u16) -> VMResult<usize> {
+		let arg = self.arg(n)?;
+		if arg > LITERAL_MAX && arg <= REG_ADDR_MAX {
+			return Ok((arg - LITERAL_MAX - 1) as usize);
 		}
+		return Err(self.invalid_operand(arg));
 	}
-	fn set_halt_with_error(&mut self, err_str:String) {
-		self.halt = true;
-		self.halt_code = err_str;
-		self.halt_err = true;
+	// shared body of the three operand arithmetic and comparison opcodes
+	fn op_binary(&mut self, f: fn(u16, u16) -> u16) -> VMResult<()> {
+		let reg = self.arg_reg(1)?;
+		let val2 = self.arg_val(2)?;
+		let val3 = self.arg_val(3)?;
+		self.set_reg(reg, f(val2, val3));
+		self.mem_ptr += 4;
+		return Ok(());
 	}
-	fn op_halt(&mut self) {
-		
-		self.halt = true;
-		self.halt_code = "Halted successfully".to_string();
-		self.halt_err = false;
+	fn op_halt(&mut self) -> VMResult<()> {
+		self.halt = Some(HaltReason::CleanHalt { addr: self.mem_ptr, opcode: 0 });
 		self.mem_ptr += 1;
+		return Ok(());
 	}
-	fn op_set(&mut self) {
-		let op_name = "OP SET";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}	
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{} error: arg1 {} is not a register address", op_name, arg1));
-		}
-		let val_result = self.val(arg2);
-		if !val_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg2 - {}", op_name, val_result.unwrap_err()));
-			return;
-		}
-		let val = val_result.unwrap();
-		let reg_set = self.reg_set(arg1, val);
-		if !reg_set.is_ok() {
-			self.set_halt_with_error(reg_set.unwrap_err().to_string());
-		}
+	fn op_set(&mut self) -> VMResult<()> {
+		let reg = self.arg_reg(1)?;
+		let val = self.arg_val(2)?;
+		self.set_reg(reg, val);
 		self.mem_ptr += 3;
+		return Ok(());
 	}
-	fn op_push(&mut self) {
-		let op_name = "OP PUSH";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		let val_result = self.val(arg1);
-		if !val_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val_result.unwrap_err()));
-			return;
-		}
-		let val = val_result.unwrap();
-		self.stack.push(val);		
+	fn op_push(&mut self) -> VMResult<()> {
+		let val = self.arg_val(1)?;
+		self.stack.push(val);
 		self.mem_ptr += 2;
+		return Ok(());
 	}
-	fn op_pop(&mut self) {
-		let op_name = "OP POP";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{} error: arg1 {} is not a register address", op_name, arg1));
-			return;
-		}
-		
-		let pop_val = self.stack.pop();
-		match pop_val {
-			Some(x) => {
-					let reg_set_result = self.reg_set(arg1, x);
-					if !reg_set_result.is_ok() {
-						self.halt = true; self.halt_code = format!("{} error: {}", op_name, reg_set_result.unwrap_err().to_string());
-					}
-				},
+	fn op_pop(&mut self) -> VMResult<()> {
+		let reg = self.arg_reg(1)?;
+		match self.stack_pop() {
+			Some(x) => self.set_reg(reg, x),
+			None if self.strictness == Strictness::Strict => return Err(self.stack_underflow()),
 			None => {
-					self.halt = true; 
-					self.halt_code = format!("{} error: empty stack", op_name)
-				},
+				self.halt = Some(HaltReason::CleanHalt { addr: self.mem_ptr, opcode: 3 });
+				return Ok(());
+			},
 		}
 		self.mem_ptr += 2;
+		return Ok(());
 	}
-	fn op_eq(&mut self) {
-		let op_name = "OP EQ";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		if val2 == val3 {
-			let reg_set_result = self.reg_set(arg1, 1);
-			if !reg_set_result.is_ok() {
-				self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-				return;
-			}
-		}
-		else {
-			let reg_set_result = self.reg_set(arg1, 0);
-			if !reg_set_result.is_ok() {
-				self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-				return;
-			}
-		}
-		self.mem_ptr += 4;
+	fn op_eq(&mut self) -> VMResult<()> {
+		return self.op_binary(|a, b| (a == b) as u16);
 	}
-	fn op_gt(&mut self) {
-		let op_name = "OP GT";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		if val2 > val3 {
-			let reg_set_result = self.reg_set(arg1, 1);
-			if !reg_set_result.is_ok() {
-				self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-				return;
-			}
-		}
-		else {
-			let reg_set_result = self.reg_set(arg1, 0);
-			if !reg_set_result.is_ok() {
-				self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-				return;
-			}
-		}
-		self.mem_ptr += 4;
+	fn op_gt(&mut self) -> VMResult<()> {
+		return self.op_binary(|a, b| (a > b) as u16);
 	}
-	fn op_jmp(&mut self) {
-		let op_name = "OP JMP";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		let val_result = self.val(arg1);
-		if !val_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val_result.unwrap_err()));
-			return;
-		}
-		let val = val_result.unwrap();
+	fn op_jmp(&mut self) -> VMResult<()> {
+		let val = self.arg_val(1)?;
 		self.mem_ptr = val;
+		return Ok(());
 	}
-	fn op_jt(&mut self) {
-		let op_name = "OP JT";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let val1_result = self.val(arg1);
-		if !val1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val1_result.unwrap_err()));
-			return;
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val1 = val1_result.unwrap();
-		let val2 = val2_result.unwrap();
+	fn op_jt(&mut self) -> VMResult<()> {
+		let val1 = self.arg_val(1)?;
+		let val2 = self.arg_val(2)?;
 		if val1 != 0 {
 			self.mem_ptr = val2;
 		}
 		else {
 			self.mem_ptr += 3;
 		}
+		return Ok(());
 	}
-	fn op_jf(&mut self) {
-		let op_name = "OP JF";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let val1_result = self.val(arg1);
-		if !val1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val1_result.unwrap_err()));
-			return;
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val1 = val1_result.unwrap();
-		let val2 = val2_result.unwrap();
+	fn op_jf(&mut self) -> VMResult<()> {
+		let val1 = self.arg_val(1)?;
+		let val2 = self.arg_val(2)?;
 		if val1 == 0 {
 			self.mem_ptr = val2;
 		}
 		else {
 			self.mem_ptr += 3;
 		}
+		return Ok(());
 	}
-	fn op_add(&mut self) {
-		let op_name = "OP ADD";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		let val2_u32 = val2 as u32;
-		let val3_u32 = val3 as u32;
-		let sum_u32 = (val2_u32 + val3_u32) % (LITERAL_MAX as u32 + 1);
-		let sum = sum_u32 as u16;
-		
-		let reg_set_result = self.reg_set(arg1, sum);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
-		}
-		self.mem_ptr += 4;
+	fn op_add(&mut self) -> VMResult<()> {
+		return self.op_binary(|a, b| ((a as u32 + b as u32) % (LITERAL_MAX as u32 + 1)) as u16);
 	}
-	fn op_mult(&mut self) {
-		let op_name = "OP MULT";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		let val2_u32 = val2 as u32;
-		let val3_u32 = val3 as u32;
-		let product_u32 = (val2_u32 * val3_u32) % (LITERAL_MAX as u32 + 1);
-		let product = product_u32 as u16;
-		let reg_set_result = self.reg_set(arg1, product);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
-		}
-		self.mem_ptr += 4;
+	fn op_mult(&mut self) -> VMResult<()> {
+		return self.op_binary(|a, b| ((a as u32 * b as u32) % (LITERAL_MAX as u32 + 1)) as u16);
 	}
-	fn op_mod(&mut self) {
-		let op_name = "OP MOD";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		let remainder = (val2 % val3) % (LITERAL_MAX + 1);
-		let reg_set_result = self.reg_set(arg1, remainder);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
+	fn op_mod(&mut self) -> VMResult<()> {
+		// operands are checked in the same order as op_binary before the divisor
+		let reg = self.arg_reg(1)?;
+		let val2 = self.arg_val(2)?;
+		let val3 = self.arg_val(3)?;
+		if val3 == 0 {
+			return Err(VMError::DivisionByZero { addr: self.mem_ptr, opcode: self.opcode() });
 		}
+		self.set_reg(reg, val2 % val3);
 		self.mem_ptr += 4;
+		return Ok(());
 	}
-	fn op_and(&mut self) {
-		let op_name = "OP AND";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		let and_val = val2 & val3;
-		let reg_set_result = self.reg_set(arg1, and_val);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
-		}
-		self.mem_ptr += 4;
+	fn op_and(&mut self) -> VMResult<()> {
+		return self.op_binary(|a, b| a & b);
 	}
-	fn op_or(&mut self) {
-		let op_name = "OP OR";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		let arg3_result = self.mem_read(self.mem_ptr + 3);
-		if !arg3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg3 - {}", op_name, arg3_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		let arg3 = arg3_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		let val3_result = self.val(arg3);
-		if !val3_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg3 - {}", op_name, val3_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let val3 = val3_result.unwrap();
-		
-		let or_val = val2 | val3;
-		let reg_set_result = self.reg_set(arg1, or_val);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
-		}
-		self.mem_ptr += 4;
+	fn op_or(&mut self) -> VMResult<()> {
+		return self.op_binary(|a, b| a | b);
 	}
-	fn op_not(&mut self) {
-		let op_name = "OP NOT";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		
-		let not_val = (!val2) & LITERAL_MAX;
-		let reg_set_result = self.reg_set(arg1, not_val);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
-		}
+	fn op_not(&mut self) -> VMResult<()> {
+		let reg = self.arg_reg(1)?;
+		let val = self.arg_val(2)?;
+		self.set_reg(reg, (!val) & LITERAL_MAX);
 		self.mem_ptr += 3;
+		return Ok(());
 	}
-	fn op_rmem(&mut self) {
-		let op_name = "OP RMEM";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		
-		let val2 = val2_result.unwrap();
-		let mem_val_result = self.mem_read(val2);
-		if !mem_val_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value from memory at address {} - {}", op_name, val2, mem_val_result.unwrap_err()));
-			return;
-		}
-		
-		let mem_val = mem_val_result.unwrap();
-		let reg_set_result = self.reg_set(arg1, mem_val);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
-		}
+	fn op_rmem(&mut self) -> VMResult<()> {
+		let reg = self.arg_reg(1)?;
+		let addr = self.arg_val(2)?;
+		let val = self.mem_read(addr)?;
+		self.set_reg(reg, val);
 		self.mem_ptr += 3;
+		return Ok(());
 	}
-	fn op_wmem(&mut self) {
-		let op_name = "OP WMEM";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg2_result = self.mem_read(self.mem_ptr + 2);
-		if !arg2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg2 - {}", op_name, arg2_result.unwrap_err()));
-			return;
-		}
-		
-		let arg1 = arg1_result.unwrap();
-		let arg2 = arg2_result.unwrap();
-		
-		let val1_result = self.val(arg1);
-		if !val1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg1 - {}", op_name, val1_result.unwrap_err()));
-			return;
-		}
-		let val2_result = self.val(arg2);
-		if !val2_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error retrieving value for arg2 - {}", op_name, val2_result.unwrap_err()));
-			return;
-		}
-		
-		let val1 = val1_result.unwrap();
-		let val2 = val2_result.unwrap();
-		let mem_write_result = self.mem_write(val1, val2);
-		if !mem_write_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: error writing value {} to memory at address {} - {}", op_name, val2, val1, mem_write_result.unwrap_err()));
-			return;
-		}
-		
+	fn op_wmem(&mut self) -> VMResult<()> {
+		let addr = self.arg_val(1)?;
+		let val = self.arg_val(2)?;
+		self.mem_write(addr, val)?;
 		self.mem_ptr += 3;
+		return Ok(());
 	}
-	fn op_call(&mut self) {
-		let op_name = "OP CALL";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		let val_result = self.val(arg1);
-		if !val_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val_result.unwrap_err()));
-			return;
-		}
-		let val = val_result.unwrap();
-		let next = self.mem_ptr + 2;
-		self.stack.push(next);
+	fn op_call(&mut self) -> VMResult<()> {
+		let val = self.arg_val(1)?;
+		let call_site = self.mem_ptr;
+		self.stack.push(call_site + 2);
 		self.mem_ptr = val;
+		self.enter_frame(call_site, val);
+		self.emit(Event::Call { call_site, entry: val });
+		return Ok(());
 	}
-	fn op_ret(&mut self) {
-		let op_name = "OP RET";
-		
-		let pop_val = self.stack.pop();
-		match pop_val {
+	fn op_ret(&mut self) -> VMResult<()> {
+		self.leave_frame();
+		match self.stack_pop() {
 			Some(x) => {
-					self.mem_ptr = x;
-				},
-			None => {
-					// this may not need to be an error state, just a halt
-					self.set_halt_with_error(format!("{} error: empty stack", op_name));
-				},
+				self.emit(Event::Return { pc: self.mem_ptr, addr: x });
+				self.mem_ptr = x;
+			},
+			// the spec defines an empty stack as a halt
+			None => self.halt = Some(HaltReason::CleanHalt { addr: self.mem_ptr, opcode: 18 }),
 		}
+		return Ok(());
 	}
-	// should maybe include a check for valid ascii values, but eh.
-	fn op_out(&mut self) {
-		let op_name = "OP OUT";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
+	fn op_out(&mut self) -> VMResult<()> {
+		let val = self.arg_val(1)?;
+		if val > 127 && self.strictness == Strictness::Strict {
+			return Err(VMError::NonAscii { addr: self.mem_ptr, opcode: 19, val });
 		}
-		let arg1 = arg1_result.unwrap();
-		let val_result = self.val(arg1);
-		if !val_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid val for arg1 - {}", op_name, val_result.unwrap_err()));
-			return;
-		}
-		let val = val_result.unwrap();
 		let chr = (val as u8) as char;
-		if self.interactive {
-			print!("{}", chr);
-		}
-		else {
-			self.output_buff.push(chr);
-			self.output_buff_index += 1;
-		}
+		self.output.write_char(chr);
+		self.emit(Event::OutputChar(chr));
 		self.mem_ptr += 2;
-	}
-	fn op_in(&mut self) {
-		let op_name = "OP IN";
-		let arg1_result = self.mem_read(self.mem_ptr + 1);
-		if !arg1_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: invalid arg1 - {}", op_name, arg1_result.unwrap_err()));
-			return;
-		}
-		let arg1 = arg1_result.unwrap();
-		if !self.is_reg_addr(arg1) {
-			self.set_halt_with_error(format!("{}: arg1 {} is not a register address", op_name, arg1));
-			return
-		}
-		// if the input buffer is empty or exhausted, read a line from stdin
-		if self.input_buff.len() == 0 || self.input_buff.len() <= self.input_buff_index {
-			if self.interactive {
-				let mut line = String::new();
-				let stdin = io::stdin();
-				stdin.read_line(&mut line).unwrap();
-				let char_vec:Vec<char> = line.chars().collect();
-				self.input_buff.clear();
-				for c in char_vec {
-					self.input_buff.push(c);
-				}
-				self.input_buff_index = 0;
+		return Ok(());
+	}
+	fn op_in(&mut self) -> VMResult<()> {
+		let reg = self.arg_reg(1)?;
+		// if the input buffer is empty or exhausted, ask the input backend for a line
+		if self.input_buff.is_empty() || self.input_buff.len() <= self.input_buff_index {
+			if !self.input_ready {
+				self.emit(Event::InputRequested { pc: self.mem_ptr });
 			}
-			else {
+			if !self.input_ready && !self.fetch_input() {
+				// resume execution after input ready
 				self.awaiting_input = true;
-				if self.input_ready  {
-					self.input_buff_index = 0;
-					self.awaiting_input = false;
-					self.input_ready = false;
-				}
-				else {
-					// resume execution after input ready
-					return;
-				}
+				return Ok(());
 			}
+			self.input_buff_index = 0;
+			self.awaiting_input = false;
+			self.input_ready = false;
 		}
-		let input_char:char = self.input_buff[self.input_buff_index];
-		self.input_buff_index += 1;
-		let input = input_char as u16;
-		
-		let reg_set_result = self.reg_set(arg1, input);
-		if !reg_set_result.is_ok() {
-			self.set_halt_with_error(format!("{} error: {}", op_name, reg_set_result.unwrap_err()));
-			return;
+		let input_char = self.input_buff[self.input_buff_index] as u32;
+		if input_char > 127 && self.strictness == Strictness::Strict {
+			return Err(VMError::NonAscii { addr: self.mem_ptr, opcode: 20, val: input_char.min(u16::MAX as u32) as u16 });
 		}
-		
+		self.input_buff_index += 1;
+		self.set_reg(reg, (input_char & LITERAL_MAX as u32) as u16);
 		self.mem_ptr += 2;
+		return Ok(());
 	}
-	fn op_noop(&mut self) {
+	fn op_noop(&mut self) -> VMResult<()> {
 		self.mem_ptr += 1;
+		return Ok(());
 	}
-	fn op_undefined(&mut self, opcode:u16) {
-		self.set_halt_with_error(format!("Undefined opcode {}", opcode));
+	fn op_undefined(&mut self, opcode:u16) -> VMResult<()> {
+		return Err(VMError::UndefinedOpcode { addr: self.mem_ptr, opcode });
 	}
-}
\ No newline at end of file
+}
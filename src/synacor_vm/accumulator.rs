@@ -0,0 +1,163 @@
+use super::SynacorVM;
+use std::collections::HashMap;
+
+// execution counts for one guest function, identified by its entry address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionProfile {
+	pub entry: u16,
+	pub calls: usize,
+	// instructions executed by the function and everything it called. Recursive
+	// calls are only counted once, by the outermost call.
+	pub inclusive: usize,
+	// instructions executed by the function itself
+	pub exclusive: usize,
+}
+
+// a function call in progress
+#[derive(Clone)]
+struct Frame {
+	entry: u16,
+	// position of the matching frame in the vm's call stack
+	depth: usize,
+	// total instruction count when the call was made
+	start: usize,
+}
+
+// a change the vm made to its call frames
+#[derive(Clone)]
+enum FrameChange {
+	Push { entry:u16, depth:usize },
+	Pop { depth:usize },
+}
+
+// histogram of memory positions for executed instructions, with per opcode
+// and per function counts. Functions follow the vm's call frames, so a RET to
+// an address the guest pushed itself does not end the function.
+#[derive(Clone, Default)]
+pub struct InstructionAccumulator {
+	acc:HashMap<u16, usize>,
+	opcodes:HashMap<u16, usize>,
+	functions:HashMap<u16, FunctionProfile>,
+	// the vm frames opened since profiling started
+	frames:Vec<Frame>,
+	// frame changes made by the instruction executing, applied once it is counted
+	pending:Vec<FrameChange>,
+	// number of frames open for each function, so recursion is not double counted
+	active:HashMap<u16, usize>,
+	total:usize,
+}
+
+impl InstructionAccumulator {
+	pub fn new() -> InstructionAccumulator {
+		return InstructionAccumulator::default();
+	}
+	pub fn clear(&mut self) {
+		*self = InstructionAccumulator::default();
+	}
+	// count an instruction, charging it to the function it started in
+	pub fn record(&mut self, pos:u16, opcode:u16) {
+		*self.acc.entry(pos).or_insert(0) += 1;
+		*self.opcodes.entry(opcode).or_insert(0) += 1;
+		self.total += 1;
+		if let Some(frame) = self.frames.last() {
+			let entry = frame.entry;
+			self.function(entry).exclusive += 1;
+		}
+		self.apply_frames();
+	}
+	pub(super) fn note_frame_push(&mut self, entry:u16, depth:usize) {
+		self.pending.push(FrameChange::Push { entry, depth });
+	}
+	pub(super) fn note_frame_pop(&mut self, depth:usize) {
+		self.pending.push(FrameChange::Pop { depth });
+	}
+	// apply the frame changes noted since the last instruction was counted
+	pub(super) fn apply_frames(&mut self) {
+		let pending:Vec<FrameChange> = self.pending.drain(..).collect();
+		for change in pending {
+			match change {
+				FrameChange::Push { entry, depth } => {
+					// frames left at this depth by an undo or load are gone
+					self.close_frames(depth);
+					self.function(entry).calls += 1;
+					*self.active.entry(entry).or_insert(0) += 1;
+					self.frames.push(Frame { entry, depth, start: self.total });
+				},
+				FrameChange::Pop { depth } => self.close_frames(depth),
+			}
+		}
+	}
+	// close the frames at depth and deeper. Frames opened before profiling
+	// started are not held, so closing them does nothing.
+	pub(super) fn close_frames(&mut self, depth:usize) {
+		while self.frames.last().map(|frame| frame.depth >= depth).unwrap_or(false) {
+			let frame = self.frames.pop().unwrap();
+			let active = self.active.get_mut(&frame.entry).unwrap();
+			*active -= 1;
+			if *active == 0 {
+				let total = self.total;
+				self.function(frame.entry).inclusive += total - frame.start;
+			}
+		}
+	}
+	fn function(&mut self, entry:u16) -> &mut FunctionProfile {
+		return self.functions.entry(entry).or_insert(FunctionProfile { entry, calls: 0, inclusive: 0, exclusive: 0 });
+	}
+	pub fn total(&self) -> usize {
+		return self.total;
+	}
+	// execution count per address, hottest first
+	pub fn pc_counts(&self) -> Vec<(u16, usize)> {
+		return sorted_counts(&self.acc);
+	}
+	// execution count per opcode, hottest first
+	pub fn opcode_counts(&self) -> Vec<(u16, usize)> {
+		return sorted_counts(&self.opcodes);
+	}
+	// function counts, highest inclusive count first. Calls still in progress
+	// are counted up to now.
+	pub fn function_counts(&self) -> Vec<FunctionProfile> {
+		let mut profile = self.clone();
+		profile.close_frames(0);
+		let mut functions:Vec<FunctionProfile> = profile.functions.values().cloned().collect();
+		functions.sort_by(|a, b| b.inclusive.cmp(&a.inclusive).then(b.exclusive.cmp(&a.exclusive)).then(a.entry.cmp(&b.entry)));
+		return functions;
+	}
+}
+
+fn sorted_counts(counts:&HashMap<u16, usize>) -> Vec<(u16, usize)> {
+	let mut sorted:Vec<(u16, usize)> = counts.iter().map(|(k, v)| (*k, *v)).collect();
+	sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+	return sorted;
+}
+
+impl SynacorVM {
+	// start counting executed instructions. Replaces any profile already being
+	// collected. Clones of the vm start without a profiler.
+	pub fn start_profile(&mut self) {
+		self.profiler.0 = Some(InstructionAccumulator::new());
+	}
+	// stop profiling and return the counts collected
+	pub fn stop_profile(&mut self) -> Option<InstructionAccumulator> {
+		return self.profiler.0.take();
+	}
+	pub fn profile(&self) -> Option<&InstructionAccumulator> {
+		return self.profiler.0.as_ref();
+	}
+	// count the instruction that just executed at pc
+	pub(super) fn profile_instruction(&mut self, pc:u16, opcode:u16) {
+		// IN waiting for input runs again once input arrives
+		if self.awaiting_input {
+			return;
+		}
+		if let Some(profiler) = self.profiler.0.as_mut() {
+			profiler.record(pc, opcode);
+		}
+	}
+	// a hook changed the call frames without executing an instruction
+	pub(super) fn profile_frames(&mut self) {
+		if let Some(profiler) = self.profiler.0.as_mut() {
+			profiler.apply_frames();
+		}
+	}
+}
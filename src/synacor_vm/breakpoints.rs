@@ -0,0 +1,106 @@
+use super::{HaltReason, REG_N};
+use std::collections::HashSet;
+
+// why execute() handed control back to the caller
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+	Halted(HaltReason),
+	AwaitingInput,
+	// about to execute the instruction at addr
+	Breakpoint { addr:u16 },
+	// the instruction at pc read memory at addr
+	MemoryRead { pc:u16, addr:u16, val:u16 },
+	// the instruction at pc wrote memory at addr
+	MemoryWrite { pc:u16, addr:u16, old:u16, new:u16 },
+	// the instruction at pc wrote register reg
+	RegisterWrite { pc:u16, reg:usize, old:u16, new:u16 },
+	// execute_for() ran its full instruction budget
+	BudgetExhausted,
+	// the run_until() predicate returned true
+	Condition,
+}
+
+// PC breakpoints stop before the instruction at the address executes.
+// Watchpoints stop after the instruction that touched the watched location
+// has completed. Only data accesses made by instructions (RMEM, WMEM and
+// register destinations) trigger watchpoints, not instruction fetches. If one
+// instruction triggers several watchpoints the last one is reported.
+#[derive(Clone, Default)]
+pub struct Breakpoints {
+	pc: HashSet<u16>,
+	mem_read: HashSet<u16>,
+	mem_write: HashSet<u16>,
+	reg_write: [bool; REG_N],
+}
+
+impl Breakpoints {
+	pub fn add_pc(&mut self, addr:u16) {
+		self.pc.insert(addr);
+	}
+	pub fn remove_pc(&mut self, addr:u16) -> bool {
+		return self.pc.remove(&addr);
+	}
+	pub fn add_read(&mut self, addr:u16) {
+		self.mem_read.insert(addr);
+	}
+	pub fn remove_read(&mut self, addr:u16) -> bool {
+		return self.mem_read.remove(&addr);
+	}
+	pub fn add_write(&mut self, addr:u16) {
+		self.mem_write.insert(addr);
+	}
+	pub fn remove_write(&mut self, addr:u16) -> bool {
+		return self.mem_write.remove(&addr);
+	}
+	// false if there is no such register
+	pub fn add_register(&mut self, reg:usize) -> bool {
+		if reg >= REG_N {
+			return false;
+		}
+		self.reg_write[reg] = true;
+		return true;
+	}
+	pub fn remove_register(&mut self, reg:usize) -> bool {
+		if reg >= REG_N || !self.reg_write[reg] {
+			return false;
+		}
+		self.reg_write[reg] = false;
+		return true;
+	}
+	pub fn clear(&mut self) {
+		*self = Breakpoints::default();
+	}
+	pub fn pc_addrs(&self) -> Vec<u16> {
+		let mut addrs:Vec<u16> = self.pc.iter().cloned().collect();
+		addrs.sort();
+		return addrs;
+	}
+	pub fn read_addrs(&self) -> Vec<u16> {
+		let mut addrs:Vec<u16> = self.mem_read.iter().cloned().collect();
+		addrs.sort();
+		return addrs;
+	}
+	pub fn write_addrs(&self) -> Vec<u16> {
+		let mut addrs:Vec<u16> = self.mem_write.iter().cloned().collect();
+		addrs.sort();
+		return addrs;
+	}
+	pub fn registers(&self) -> Vec<usize> {
+		return (0..REG_N).filter(|r| self.reg_write[*r]).collect();
+	}
+	pub(super) fn is_empty(&self) -> bool {
+		return self.pc.is_empty() && self.mem_read.is_empty() && self.mem_write.is_empty() && !self.reg_write.contains(&true);
+	}
+	pub(super) fn has_pc(&self, addr:u16) -> bool {
+		return !self.pc.is_empty() && self.pc.contains(&addr);
+	}
+	pub(super) fn has_read(&self, addr:u16) -> bool {
+		return !self.mem_read.is_empty() && self.mem_read.contains(&addr);
+	}
+	pub(super) fn has_write(&self, addr:u16) -> bool {
+		return !self.mem_write.is_empty() && self.mem_write.contains(&addr);
+	}
+	pub(super) fn has_register(&self, reg:usize) -> bool {
+		return self.reg_write[reg];
+	}
+}
@@ -0,0 +1,168 @@
+use super::SynacorVM;
+use std::collections::HashMap;
+
+// a guest function call in progress
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+	// address of the CALL instruction
+	pub call_site: u16,
+	// address called
+	pub entry: u16,
+	pub return_addr: u16,
+	// position of the return address on the guest stack
+	stack_pos: usize,
+}
+
+// caller to callee call counts and recursion depths, collected from every CALL
+// executed. Calls made outside any function have no caller. Counts are not
+// taken back when the undo journal rewinds the vm.
+#[derive(Clone, Default)]
+pub struct CallGraph {
+	edges: HashMap<(Option<u16>, u16), u64>,
+	// frames currently open for each function
+	depth: HashMap<u16, usize>,
+	max_depth: HashMap<u16, usize>,
+	max_call_depth: usize,
+}
+
+impl CallGraph {
+	fn call(&mut self, caller:Option<u16>, entry:u16, call_depth:usize) {
+		*self.edges.entry((caller, entry)).or_insert(0) += 1;
+		let depth = self.depth.entry(entry).or_insert(0);
+		*depth += 1;
+		let max = self.max_depth.entry(entry).or_insert(0);
+		if *depth > *max {
+			*max = *depth;
+		}
+		if call_depth > self.max_call_depth {
+			self.max_call_depth = call_depth;
+		}
+	}
+	fn ret(&mut self, entry:u16) {
+		if let Some(depth) = self.depth.get_mut(&entry) {
+			*depth = depth.saturating_sub(1);
+		}
+	}
+	// (caller, callee, calls), most called first
+	pub fn edges(&self) -> Vec<(Option<u16>, u16, u64)> {
+		let mut edges:Vec<(Option<u16>, u16, u64)> = self.edges.iter().map(|((caller, callee), count)| (*caller, *callee, *count)).collect();
+		edges.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+		return edges;
+	}
+	// number of times entry was called from anywhere
+	pub fn calls(&self, entry:u16) -> u64 {
+		return self.edges.iter().filter(|((_, callee), _)| *callee == entry).map(|(_, count)| *count).sum();
+	}
+	// deepest recursion seen for a function: 1 if it never called itself, even indirectly
+	pub fn max_recursion(&self, entry:u16) -> usize {
+		return *self.max_depth.get(&entry).unwrap_or(&0);
+	}
+	// (function, deepest recursion), deepest first
+	pub fn max_recursion_depths(&self) -> Vec<(u16, usize)> {
+		let mut depths:Vec<(u16, usize)> = self.max_depth.iter().map(|(entry, depth)| (*entry, *depth)).collect();
+		depths.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+		return depths;
+	}
+	// deepest call stack seen
+	pub fn max_call_depth(&self) -> usize {
+		return self.max_call_depth;
+	}
+	// Graphviz DOT with one node per function, labelled with its deepest
+	// recursion, and one edge per caller and callee pair, labelled with the call count
+	pub fn to_dot(&self) -> String {
+		let mut out = String::from("digraph callgraph {\n");
+		out.push_str("\troot [label=\"root\"];\n");
+		let mut entries:Vec<u16> = self.max_depth.keys().cloned().collect();
+		entries.sort();
+		for entry in entries {
+			out.push_str(&format!("\tfn_{} [label=\"fn_{}\\ncalls {}\\nmax depth {}\"];\n", entry, entry, self.calls(entry), self.max_recursion(entry)));
+		}
+		for (caller, callee, count) in self.edges() {
+			let from = match caller {
+				Some(caller) => format!("fn_{}", caller),
+				None => "root".to_string(),
+			};
+			out.push_str(&format!("\t{} -> fn_{} [label=\"{}\"];\n", from, callee, count));
+		}
+		out.push_str("}\n");
+		return out;
+	}
+}
+
+impl SynacorVM {
+	// the guest call chain, innermost call first
+	pub fn backtrace(&self) -> Vec<CallFrame> {
+		return self.frames.iter().rev().cloned().collect();
+	}
+	pub fn call_depth(&self) -> usize {
+		return self.frames.len();
+	}
+	pub fn call_graph(&self) -> &CallGraph {
+		return &self.call_graph;
+	}
+	pub fn reset_call_graph(&mut self) {
+		self.call_graph = CallGraph::default();
+		for frame in self.frames.iter() {
+			*self.call_graph.depth.entry(frame.entry).or_insert(0) += 1;
+		}
+	}
+	// a CALL at call_site has pushed its return address and jumped to entry
+	pub(super) fn enter_frame(&mut self, call_site:u16, entry:u16) {
+		let caller = self.frames.last().map(|frame| frame.entry);
+		let frame = CallFrame { call_site, entry, return_addr: call_site + 2, stack_pos: self.stack.len() - 1 };
+		self.frames.push(frame);
+		self.call_graph.call(caller, entry, self.frames.len());
+		if let Some(journal) = self.journal.0.as_mut() {
+			journal.note_frame_push();
+		}
+		if let Some(profiler) = self.profiler.0.as_mut() {
+			profiler.note_frame_push(entry, self.frames.len() - 1);
+		}
+	}
+	// a return is about to pop the top of the guest stack. Frames whose return
+	// address has already been removed from the stack are dropped on the way,
+	// and a return to an address the guest pushed itself leaves the frames alone.
+	pub(super) fn leave_frame(&mut self) {
+		if self.stack.is_empty() {
+			return;
+		}
+		let pos = self.stack.len() - 1;
+		while let Some(frame) = self.frames.last() {
+			if frame.stack_pos < pos {
+				return;
+			}
+			let frame = self.frames.pop().unwrap();
+			self.call_graph.ret(frame.entry);
+			let matched = frame.stack_pos == pos;
+			if let Some(journal) = self.journal.0.as_mut() {
+				journal.note_frame_pop(frame);
+			}
+			if let Some(profiler) = self.profiler.0.as_mut() {
+				profiler.note_frame_pop(self.frames.len());
+			}
+			if matched {
+				return;
+			}
+		}
+	}
+	// take back the frame changes of an undone instruction
+	pub(super) fn restore_frames(&mut self, pushed:bool, popped:Vec<CallFrame>) {
+		if pushed {
+			if let Some(frame) = self.frames.pop() {
+				self.call_graph.ret(frame.entry);
+			}
+		}
+		for frame in popped.into_iter().rev() {
+			*self.call_graph.depth.entry(frame.entry).or_insert(0) += 1;
+			self.frames.push(frame);
+		}
+	}
+	// frames cannot be recovered from a bare guest stack
+	pub(super) fn clear_frames(&mut self) {
+		self.frames.clear();
+		self.call_graph.depth.clear();
+		if let Some(profiler) = self.profiler.0.as_mut() {
+			profiler.close_frames(0);
+		}
+	}
+}
@@ -0,0 +1,257 @@
+// Backends for the IN and OUT instructions. The vm asks its Input for a line
+// whenever the game reads past the end of the current line, and hands every
+// character the game prints to its Output.
+//
+// Interactive mode is stdin and stdout. The default non-interactive mode is a
+// BufferInput that never has a line ready, so the vm stops with
+// StopReason::AwaitingInput until set_input_line() supplies one, and a
+// BufferOutput collected with output_line().
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, Sender};
+
+pub trait Input: Send {
+	// the next line of input, or None if there is none available
+	fn read_line(&mut self) -> Option<String>;
+	fn box_clone(&self) -> Box<dyn Input>;
+}
+
+pub trait Output: Send {
+	fn write_char(&mut self, c:char);
+	// returns the first error encountered writing output, if any
+	fn flush(&mut self) -> io::Result<()> {
+		return Ok(());
+	}
+	// output held for the caller, for backends that keep it
+	fn buffer(&self) -> Option<&Vec<char>> {
+		return None;
+	}
+	fn buffer_mut(&mut self) -> Option<&mut Vec<char>> {
+		return None;
+	}
+	fn box_clone(&self) -> Box<dyn Output>;
+}
+
+impl Clone for Box<dyn Input> {
+	fn clone(&self) -> Box<dyn Input> {
+		return self.box_clone();
+	}
+}
+
+impl Clone for Box<dyn Output> {
+	fn clone(&self) -> Box<dyn Output> {
+		return self.box_clone();
+	}
+}
+
+// reads lines from the terminal, blocking until one is entered. Returns None at end of input.
+#[derive(Clone, Default)]
+pub struct StdinInput;
+
+impl Input for StdinInput {
+	fn read_line(&mut self) -> Option<String> {
+		let mut line = String::new();
+		return match io::stdin().read_line(&mut line) {
+			Ok(0) | Err(_) => None,
+			Ok(_) => Some(line),
+		};
+	}
+	fn box_clone(&self) -> Box<dyn Input> {
+		return Box::new(self.clone());
+	}
+}
+
+#[derive(Clone, Default)]
+pub struct StdoutOutput;
+
+impl Output for StdoutOutput {
+	fn write_char(&mut self, c:char) {
+		print!("{}", c);
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		return io::stdout().flush();
+	}
+	fn box_clone(&self) -> Box<dyn Output> {
+		return Box::new(self.clone());
+	}
+}
+
+// a queue of lines prepared in advance
+#[derive(Clone, Default)]
+pub struct BufferInput {
+	lines: VecDeque<String>,
+}
+
+impl BufferInput {
+	pub fn new() -> BufferInput {
+		return BufferInput::default();
+	}
+	pub fn from_lines(lines:&[&str]) -> BufferInput {
+		let mut input = BufferInput::new();
+		for line in lines {
+			input.push_line(line);
+		}
+		return input;
+	}
+	pub fn push_line(&mut self, line:&str) {
+		self.lines.push_back(line.to_string());
+	}
+}
+
+impl Input for BufferInput {
+	fn read_line(&mut self) -> Option<String> {
+		return self.lines.pop_front();
+	}
+	fn box_clone(&self) -> Box<dyn Input> {
+		return Box::new(self.clone());
+	}
+}
+
+#[derive(Clone, Default)]
+pub struct BufferOutput {
+	chars: Vec<char>,
+}
+
+impl BufferOutput {
+	pub fn new() -> BufferOutput {
+		return BufferOutput::default();
+	}
+}
+
+impl Output for BufferOutput {
+	fn write_char(&mut self, c:char) {
+		self.chars.push(c);
+	}
+	fn buffer(&self) -> Option<&Vec<char>> {
+		return Some(&self.chars);
+	}
+	fn buffer_mut(&mut self) -> Option<&mut Vec<char>> {
+		return Some(&mut self.chars);
+	}
+	fn box_clone(&self) -> Box<dyn Output> {
+		return Box::new(self.clone());
+	}
+}
+
+// reads lines from a file until the end of it. Clones share the file position.
+#[derive(Clone)]
+pub struct FileInput {
+	reader: Arc<Mutex<BufReader<File>>>,
+}
+
+impl FileInput {
+	pub fn open(path:&str) -> io::Result<FileInput> {
+		let file = File::open(path)?;
+		return Ok(FileInput { reader: Arc::new(Mutex::new(BufReader::new(file))) });
+	}
+}
+
+impl Input for FileInput {
+	fn read_line(&mut self) -> Option<String> {
+		let mut line = String::new();
+		return match self.reader.lock().unwrap().read_line(&mut line) {
+			Ok(0) | Err(_) => None,
+			Ok(_) => Some(line),
+		};
+	}
+	fn box_clone(&self) -> Box<dyn Input> {
+		return Box::new(self.clone());
+	}
+}
+
+struct FileSink {
+	writer: BufWriter<File>,
+	error: Option<io::Error>,
+}
+
+// writes output to a file. Clones write to the same file.
+#[derive(Clone)]
+pub struct FileOutput {
+	sink: Arc<Mutex<FileSink>>,
+}
+
+impl FileOutput {
+	pub fn create(path:&str) -> io::Result<FileOutput> {
+		let file = File::create(path)?;
+		let sink = FileSink { writer: BufWriter::new(file), error: None };
+		return Ok(FileOutput { sink: Arc::new(Mutex::new(sink)) });
+	}
+}
+
+impl Output for FileOutput {
+	fn write_char(&mut self, c:char) {
+		let mut sink = self.sink.lock().unwrap();
+		if sink.error.is_some() {
+			return;
+		}
+		let mut bytes = [0u8; 4];
+		if let Err(err) = sink.writer.write_all(c.encode_utf8(&mut bytes).as_bytes()) {
+			sink.error = Some(err);
+		}
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		let mut sink = self.sink.lock().unwrap();
+		if let Some(err) = sink.error.take() {
+			return Err(err);
+		}
+		return sink.writer.flush();
+	}
+	fn box_clone(&self) -> Box<dyn Output> {
+		return Box::new(self.clone());
+	}
+}
+
+// receives lines from another thread. A blocking input waits for the next
+// line; a non-blocking one reports no input when none has arrived yet. Either
+// returns None once every sender is gone. Clones share the receiver.
+#[derive(Clone)]
+pub struct ChannelInput {
+	rx: Arc<Mutex<Receiver<String>>>,
+	blocking: bool,
+}
+
+impl ChannelInput {
+	pub fn new(rx:Receiver<String>) -> ChannelInput {
+		return ChannelInput { rx: Arc::new(Mutex::new(rx)), blocking: true };
+	}
+	pub fn nonblocking(rx:Receiver<String>) -> ChannelInput {
+		return ChannelInput { rx: Arc::new(Mutex::new(rx)), blocking: false };
+	}
+}
+
+impl Input for ChannelInput {
+	fn read_line(&mut self) -> Option<String> {
+		let rx = self.rx.lock().unwrap();
+		if self.blocking {
+			return rx.recv().ok();
+		}
+		return rx.try_recv().ok();
+	}
+	fn box_clone(&self) -> Box<dyn Input> {
+		return Box::new(self.clone());
+	}
+}
+
+// sends each character to another thread. Characters are dropped once the receiver is gone.
+#[derive(Clone)]
+pub struct ChannelOutput {
+	tx: Sender<char>,
+}
+
+impl ChannelOutput {
+	pub fn new(tx:Sender<char>) -> ChannelOutput {
+		return ChannelOutput { tx };
+	}
+}
+
+impl Output for ChannelOutput {
+	fn write_char(&mut self, c:char) {
+		let _ = self.tx.send(c);
+	}
+	fn box_clone(&self) -> Box<dyn Output> {
+		return Box::new(self.clone());
+	}
+}
@@ -0,0 +1,73 @@
+use super::{SynacorVM, REG_N};
+use std::fmt;
+
+// everything that differs between two vm states, as (before, after) pairs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VmDiff {
+	pub pc: Option<(u16, u16)>,
+	// (register, before, after)
+	pub registers: Vec<(usize, u16, u16)>,
+	// (address, before, after)
+	pub memory: Vec<(u16, u16, u16)>,
+	// (position from the bottom, before, after). None where one stack is shorter.
+	pub stack: Vec<(usize, Option<u16>, Option<u16>)>,
+}
+
+impl VmDiff {
+	pub fn is_empty(&self) -> bool {
+		return self.pc.is_none() && self.registers.len() == 0 && self.memory.len() == 0 && self.stack.len() == 0;
+	}
+}
+
+fn stack_entry(val:Option<u16>) -> String {
+	return match val {
+		Some(val) => val.to_string(),
+		None => "-".to_string(),
+	};
+}
+
+impl fmt::Display for VmDiff {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.is_empty() {
+			return writeln!(f, "No differences");
+		}
+		if let Some((before, after)) = self.pc {
+			writeln!(f, "pc: {} -> {}", before, after)?;
+		}
+		for (reg, before, after) in self.registers.iter() {
+			writeln!(f, "r{}: {} -> {}", reg, before, after)?;
+		}
+		for (addr, before, after) in self.memory.iter() {
+			writeln!(f, "mem[{}]: {} -> {}", addr, before, after)?;
+		}
+		for (pos, before, after) in self.stack.iter() {
+			writeln!(f, "stack[{}]: {} -> {}", pos, stack_entry(*before), stack_entry(*after))?;
+		}
+		return Ok(());
+	}
+}
+
+impl SynacorVM {
+	// what changed going from this vm to other. Comparing a vm with a clone
+	// taken earlier only has to look at the memory pages written since.
+	pub fn diff(&self, other:&SynacorVM) -> VmDiff {
+		let mut diff = VmDiff::default();
+		if self.mem_ptr != other.mem_ptr {
+			diff.pc = Some((self.mem_ptr, other.mem_ptr));
+		}
+		for reg in 0..REG_N {
+			if self.reg[reg] != other.reg[reg] {
+				diff.registers.push((reg, self.reg[reg], other.reg[reg]));
+			}
+		}
+		diff.memory = self.mem.diff(&other.mem);
+		for pos in 0..self.stack.len().max(other.stack.len()) {
+			let before = self.stack.get(pos).cloned();
+			let after = other.stack.get(pos).cloned();
+			if before != after {
+				diff.stack.push((pos, before, after));
+			}
+		}
+		return diff;
+	}
+}
@@ -0,0 +1,86 @@
+// Typed notifications of what the guest program does, delivered to observers
+// as it runs. Observers see forward execution only: instructions taken back
+// with the undo journal are not reported again.
+use super::{SynacorVM, HaltReason};
+use std::sync::mpsc::Sender;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+	OutputChar(char),
+	// a completed line of output, without the newline
+	OutputLine(String),
+	// IN at pc needs a new line of input
+	InputRequested { pc:u16 },
+	// a line of input was handed to the game
+	InputConsumed(String),
+	Call { call_site:u16, entry:u16 },
+	// RET at pc, or a hook returning in its place, went back to addr
+	Return { pc:u16, addr:u16 },
+	MemoryWrite { pc:u16, addr:u16, old:u16, new:u16 },
+	Halted(HaltReason),
+}
+
+pub trait Observer: Send {
+	fn notify(&mut self, event:&Event);
+}
+
+impl<F: FnMut(&Event) + Send> Observer for F {
+	fn notify(&mut self, event:&Event) {
+		self(event);
+	}
+}
+
+// forwards every event to another thread. Events are dropped once the receiver is gone.
+impl Observer for Sender<Event> {
+	fn notify(&mut self, event:&Event) {
+		let _ = self.send(event.clone());
+	}
+}
+
+#[derive(Default)]
+pub(super) struct Observers {
+	list: Vec<(usize, Box<dyn Observer>)>,
+	next_id: usize,
+	// output since the last newline, for OutputLine
+	line: String,
+}
+
+impl SynacorVM {
+	// add an observer, returning an id for unsubscribe(). Clones of the vm
+	// start without observers.
+	pub fn subscribe(&mut self, observer:Box<dyn Observer>) -> usize {
+		let observers = self.observers.0.get_or_insert_with(Observers::default);
+		let id = observers.next_id;
+		observers.next_id += 1;
+		observers.list.push((id, observer));
+		return id;
+	}
+	pub fn unsubscribe(&mut self, id:usize) -> bool {
+		let observers = match self.observers.0.as_mut() {
+			Some(observers) => observers,
+			None => return false,
+		};
+		let len = observers.list.len();
+		observers.list.retain(|(observer_id, _)| *observer_id != id);
+		return observers.list.len() < len;
+	}
+	// does nothing until an observer has subscribed
+	pub(super) fn emit(&mut self, event:Event) {
+		let observers = match self.observers.0.as_mut() {
+			Some(observers) => observers,
+			None => return,
+		};
+		for (_, observer) in observers.list.iter_mut() {
+			observer.notify(&event);
+		}
+		if let Event::OutputChar(c) = event {
+			if c == '\n' {
+				let line = std::mem::take(&mut observers.line);
+				self.emit(Event::OutputLine(line));
+			}
+			else {
+				observers.line.push(c);
+			}
+		}
+	}
+}
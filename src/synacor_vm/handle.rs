@@ -0,0 +1,223 @@
+// Runs a vm on a thread of its own. Commands go in over one channel and
+// events come back over another, so a GUI or automation tool can drive the
+// game without blocking on it.
+use super::{SynacorVM, HaltReason, StopReason, BufferInput, BufferOutput, REG_N};
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread;
+use std::thread::JoinHandle;
+
+// instructions run between checks for new commands while the vm is running
+const SLICE: u64 = 100000;
+
+pub enum VmCommand {
+	// a line of game input. Lines sent before the game asks for them are queued.
+	Input(String),
+	// run until the game waits for input, halts or hits a breakpoint
+	Resume,
+	Pause,
+	// run at most n instructions, then pause
+	Step(u64),
+	// send back a copy of the vm
+	Snapshot,
+	Registers,
+	// stop the thread. Dropping the handle does the same.
+	Shutdown,
+}
+
+pub enum VmEvent {
+	// text the game printed since the last Output event
+	Output(String),
+	// the game is waiting for input and none is queued
+	AwaitingInput,
+	Halted(HaltReason),
+	// a breakpoint or watchpoint stopped the vm, which is now paused
+	Breakpoint(StopReason),
+	Paused { pc:u16 },
+	// a Step command finished without anything else stopping the vm
+	Stepped { pc:u16 },
+	// the copy starts without a tracer, undo journal or profiler
+	Snapshot(Box<SynacorVM>),
+	Registers { pc:u16, registers:[u16; REG_N], stack:Vec<u16> },
+}
+
+pub struct VmHandle {
+	commands: Sender<VmCommand>,
+	events: Receiver<VmEvent>,
+	thread: Option<JoinHandle<SynacorVM>>,
+}
+
+impl VmHandle {
+	// move vm onto a new thread and start running it. The vm is switched to
+	// non-interactive mode with in-memory buffers in place of whatever input
+	// and output it had, since game I/O now goes through the channels.
+	pub fn spawn(mut vm:SynacorVM) -> VmHandle {
+		vm.set_interactive(false);
+		vm.set_input(Box::new(BufferInput::new()));
+		vm.set_output(Box::new(BufferOutput::new()));
+		let (command_tx, command_rx) = mpsc::channel();
+		let (event_tx, event_rx) = mpsc::channel();
+		let thread = thread::spawn(move || {
+			let mut worker = Worker { vm, events: event_tx, pending: VecDeque::new(), running: true };
+			worker.run(command_rx);
+			return worker.vm;
+		});
+		return VmHandle { commands: command_tx, events: event_rx, thread: Some(thread) };
+	}
+	// returns false if the vm thread has stopped
+	pub fn send(&self, command:VmCommand) -> bool {
+		return self.commands.send(command).is_ok();
+	}
+	pub fn input_line(&self, line:&str) -> bool {
+		return self.send(VmCommand::Input(line.to_string()));
+	}
+	pub fn resume(&self) -> bool {
+		return self.send(VmCommand::Resume);
+	}
+	pub fn pause(&self) -> bool {
+		return self.send(VmCommand::Pause);
+	}
+	pub fn step(&self, count:u64) -> bool {
+		return self.send(VmCommand::Step(count));
+	}
+	pub fn snapshot(&self) -> bool {
+		return self.send(VmCommand::Snapshot);
+	}
+	pub fn registers(&self) -> bool {
+		return self.send(VmCommand::Registers);
+	}
+	pub fn events(&self) -> &Receiver<VmEvent> {
+		return &self.events;
+	}
+	// the next event if one is ready
+	pub fn try_event(&self) -> Option<VmEvent> {
+		return self.events.try_recv().ok();
+	}
+	// wait for the next event. None once the vm thread has stopped.
+	pub fn wait_event(&self) -> Option<VmEvent> {
+		return self.events.recv().ok();
+	}
+	// stop the thread and take the vm back. None if the thread panicked.
+	pub fn join(mut self) -> Option<SynacorVM> {
+		let _ = self.commands.send(VmCommand::Shutdown);
+		return self.thread.take().and_then(|thread| thread.join().ok());
+	}
+}
+
+impl Drop for VmHandle {
+	fn drop(&mut self) {
+		if let Some(thread) = self.thread.take() {
+			let _ = self.commands.send(VmCommand::Shutdown);
+			let _ = thread.join();
+		}
+	}
+}
+
+struct Worker {
+	vm: SynacorVM,
+	events: Sender<VmEvent>,
+	// input lines received before the game asked for them
+	pending: VecDeque<String>,
+	running: bool,
+}
+
+impl Worker {
+	fn run(&mut self, commands:Receiver<VmCommand>) {
+		loop {
+			// block while paused, only poll between slices while running
+			let command = if self.running {
+				match commands.try_recv() {
+					Ok(command) => Some(command),
+					Err(TryRecvError::Empty) => None,
+					Err(TryRecvError::Disconnected) => return,
+				}
+			}
+			else {
+				match commands.recv() {
+					Ok(command) => Some(command),
+					Err(_) => return,
+				}
+			};
+			match command {
+				Some(VmCommand::Shutdown) => return,
+				Some(command) => self.handle(command),
+				None => self.run_slice(),
+			}
+		}
+	}
+	fn handle(&mut self, command:VmCommand) {
+		match command {
+			VmCommand::Input(line) => {
+				self.pending.push_back(line);
+				if self.vm.is_awaiting_input() {
+					self.running = true;
+				}
+			},
+			VmCommand::Resume => self.running = true,
+			VmCommand::Pause => {
+				if self.running {
+					self.running = false;
+					self.emit(VmEvent::Paused { pc: self.vm.get_mem_ptr() });
+				}
+			},
+			VmCommand::Step(count) => {
+				self.running = false;
+				self.feed_input();
+				let stop = self.vm.execute_for(count);
+				self.emit_output();
+				match stop {
+					StopReason::BudgetExhausted => self.emit(VmEvent::Stepped { pc: self.vm.get_mem_ptr() }),
+					stop => self.report_stop(stop),
+				}
+			},
+			VmCommand::Snapshot => self.emit(VmEvent::Snapshot(Box::new(self.vm.clone()))),
+			VmCommand::Registers => {
+				let mut registers = [0u16; REG_N];
+				for (reg, val) in registers.iter_mut().enumerate() {
+					*val = self.vm.get_register(reg);
+				}
+				self.emit(VmEvent::Registers { pc: self.vm.get_mem_ptr(), registers, stack: self.vm.get_stack().to_vec() });
+			},
+			VmCommand::Shutdown => (),
+		}
+	}
+	fn run_slice(&mut self) {
+		self.feed_input();
+		let stop = self.vm.execute_for(SLICE);
+		self.emit_output();
+		match stop {
+			StopReason::BudgetExhausted => (),
+			// the next slice picks up the next queued line
+			StopReason::AwaitingInput if !self.pending.is_empty() => (),
+			stop => {
+				self.running = false;
+				self.report_stop(stop);
+			},
+		}
+	}
+	fn feed_input(&mut self) {
+		if self.vm.is_awaiting_input() {
+			if let Some(line) = self.pending.pop_front() {
+				self.vm.set_input_line(&line);
+			}
+		}
+	}
+	fn report_stop(&mut self, stop:StopReason) {
+		match stop {
+			StopReason::Halted(reason) => self.emit(VmEvent::Halted(reason)),
+			StopReason::AwaitingInput => self.emit(VmEvent::AwaitingInput),
+			stop => self.emit(VmEvent::Breakpoint(stop)),
+		}
+	}
+	fn emit_output(&mut self) {
+		let text = self.vm.output_line(true);
+		if !text.is_empty() {
+			self.emit(VmEvent::Output(text));
+		}
+	}
+	// events for a handle that has gone away are dropped
+	fn emit(&self, event:VmEvent) {
+		let _ = self.events.send(event);
+	}
+}
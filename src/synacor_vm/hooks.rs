@@ -0,0 +1,101 @@
+use super::{SynacorVM, HaltReason, OPCODES, Event};
+use std::sync::Arc;
+
+// what the vm does after a hook has run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookAction {
+	// execute the instruction at the hooked address as normal
+	Continue,
+	// skip the instruction at the hooked address
+	Skip,
+	// return from the guest function as RET would, halting if the stack is
+	// empty. Hook the first instruction of a function to replace it with
+	// native code.
+	Return,
+	// continue execution at an address
+	Jump(u16),
+}
+
+// the machine state a hook may inspect and change
+pub struct VmState<'a> {
+	vm: &'a mut SynacorVM,
+}
+
+impl<'a> VmState<'a> {
+	pub fn pc(&self) -> u16 {
+		return self.vm.mem_ptr;
+	}
+	pub fn register(&self, index:usize) -> u16 {
+		return self.vm.get_register(index);
+	}
+	pub fn set_register(&mut self, index:usize, val:u16) {
+		self.vm.set_register(index, val);
+	}
+	pub fn mem(&self, addr:u16) -> Option<u16> {
+		return self.vm.get_mem(addr);
+	}
+	pub fn set_mem(&mut self, addr:u16, val:u16) -> bool {
+		return self.vm.set_mem(addr, val);
+	}
+	pub fn stack(&self) -> &[u16] {
+		return &self.vm.stack;
+	}
+	pub fn push(&mut self, val:u16) {
+		self.vm.stack.push(val);
+	}
+	pub fn pop(&mut self) -> Option<u16> {
+		return self.vm.stack.pop();
+	}
+}
+
+pub(super) type Hook = Arc<dyn Fn(&mut VmState) -> HookAction + Send + Sync>;
+
+impl SynacorVM {
+	// run hook whenever execution reaches addr, before the instruction there
+	// executes. Replaces any hook already at addr. Clones of the vm share its
+	// hooks. Changes a hook makes are not recorded in the undo journal or trace.
+	pub fn register_hook<F>(&mut self, addr:u16, hook:F) where F: Fn(&mut VmState) -> HookAction + Send + Sync + 'static {
+		self.hooks.insert(addr, Arc::new(hook));
+	}
+	pub fn remove_hook(&mut self, addr:u16) -> bool {
+		return self.hooks.remove(&addr).is_some();
+	}
+	pub fn hook_addrs(&self) -> Vec<u16> {
+		let mut addrs:Vec<u16> = self.hooks.keys().cloned().collect();
+		addrs.sort();
+		return addrs;
+	}
+	// run the hook at the program counter, if any. Returns true if the hook
+	// took the place of the instruction there.
+	pub(super) fn run_hook(&mut self) -> bool {
+		let hook = match self.hooks.get(&self.mem_ptr) {
+			Some(hook) => hook.clone(),
+			None => return false,
+		};
+		let addr = self.mem_ptr;
+		let action = hook(&mut VmState { vm: self });
+		match action {
+			HookAction::Continue => return false,
+			HookAction::Skip => {
+				let opcode = self.mem.read(addr);
+				let len = OPCODES.get(opcode as usize).map(|op| op.1 + 1).unwrap_or(1);
+				self.mem_ptr = addr + len as u16;
+			},
+			HookAction::Return => {
+				self.leave_frame();
+				match self.stack.pop() {
+					Some(ret) => {
+						self.emit(Event::Return { pc: addr, addr: ret });
+						self.mem_ptr = ret;
+					},
+					// an empty stack halts, as it does for RET
+					None => self.halt = Some(HaltReason::CleanHalt { addr, opcode: 18 }),
+				}
+				self.profile_frames();
+			},
+			HookAction::Jump(target) => self.mem_ptr = target,
+		}
+		return true;
+	}
+}
+
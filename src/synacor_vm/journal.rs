@@ -0,0 +1,239 @@
+use super::{SynacorVM, CallFrame, HaltReason, REG_N};
+use std::collections::VecDeque;
+
+// state an instruction may change, captured before it executes
+struct InstructionEntry {
+	pc: u16,
+	reg: [u16; REG_N],
+	stack_len: usize,
+	// value the instruction removed from the stack
+	popped: Option<u16>,
+	// address written and its previous value
+	write: Option<(u16, u16)>,
+	// call frame opened by CALL
+	frame_pushed: bool,
+	// call frames closed by RET, innermost first
+	frames_popped: Vec<CallFrame>,
+	output_len: usize,
+	input_buff_index: usize,
+	awaiting_input: bool,
+	input_ready: bool,
+	halt: Option<HaltReason>,
+}
+
+// input state replaced when a line of game input is supplied
+struct CommandEntry {
+	input_buff: Vec<char>,
+	input_buff_index: usize,
+	awaiting_input: bool,
+	input_ready: bool,
+}
+
+enum JournalEntry {
+	Instruction(InstructionEntry),
+	Command(CommandEntry),
+}
+
+// Undo journal of previous register, memory, stack and I/O state. Once it
+// holds capacity entries the oldest are dropped, so the vm can be rewound at
+// most capacity instructions. Only output held in an output buffer is taken
+// back, and lines already read from the input backend are not returned to it.
+pub(super) struct Journal {
+	entries: VecDeque<JournalEntry>,
+	capacity: usize,
+	// entry for the instruction being executed
+	pending: Option<InstructionEntry>,
+}
+
+impl Journal {
+	pub(super) fn new(capacity:usize) -> Journal {
+		return Journal { entries: VecDeque::new(), capacity, pending: None };
+	}
+	fn push(&mut self, entry:JournalEntry) {
+		if self.capacity == 0 {
+			return;
+		}
+		if self.entries.len() >= self.capacity {
+			self.entries.pop_front();
+		}
+		self.entries.push_back(entry);
+	}
+	pub(super) fn note_pop(&mut self, val:u16) {
+		if let Some(entry) = self.pending.as_mut() {
+			entry.popped = Some(val);
+		}
+	}
+	pub(super) fn note_write(&mut self, addr:u16, old:u16) {
+		if let Some(entry) = self.pending.as_mut() {
+			entry.write = Some((addr, old));
+		}
+	}
+	pub(super) fn note_frame_push(&mut self) {
+		if let Some(entry) = self.pending.as_mut() {
+			entry.frame_pushed = true;
+		}
+	}
+	pub(super) fn note_frame_pop(&mut self, frame:CallFrame) {
+		if let Some(entry) = self.pending.as_mut() {
+			entry.frames_popped.push(frame);
+		}
+	}
+	// the pending instruction completed
+	fn commit(&mut self) {
+		if let Some(entry) = self.pending.take() {
+			self.push(JournalEntry::Instruction(entry));
+		}
+	}
+	// the pending instruction did not complete, e.g. IN waiting for input
+	fn discard(&mut self) {
+		self.pending = None;
+	}
+}
+
+impl SynacorVM {
+	// start recording an undo journal holding up to capacity entries.
+	// Clones of the vm start without a journal.
+	pub fn enable_journal(&mut self, capacity:usize) {
+		self.journal.0 = Some(Journal::new(capacity));
+	}
+	pub fn disable_journal(&mut self) {
+		self.journal.0 = None;
+	}
+	// number of instructions and commands that can be undone
+	pub fn journal_len(&self) -> usize {
+		return match &self.journal.0 {
+			Some(journal) => journal.entries.len(),
+			None => 0,
+		};
+	}
+	// forget everything recorded so far, keeping the journal enabled. Called
+	// when the whole machine state is replaced, since undoing past that point
+	// would mix the old state into the new one.
+	pub(super) fn clear_journal(&mut self) {
+		if let Some(journal) = self.journal.0.as_mut() {
+			journal.entries.clear();
+			journal.pending = None;
+		}
+	}
+	pub(super) fn journal_begin(&mut self) {
+		if self.journal.0.is_none() {
+			return;
+		}
+		let entry = InstructionEntry {
+			pc: self.mem_ptr,
+			reg: self.reg,
+			stack_len: self.stack.len(),
+			popped: None,
+			write: None,
+			frame_pushed: false,
+			frames_popped: Vec::new(),
+			output_len: self.output.buffer().map(|buffer| buffer.len()).unwrap_or(0),
+			input_buff_index: self.input_buff_index,
+			awaiting_input: self.awaiting_input,
+			input_ready: self.input_ready,
+			halt: self.halt.clone(),
+		};
+		self.journal.0.as_mut().unwrap().pending = Some(entry);
+	}
+	pub(super) fn journal_end(&mut self) {
+		if let Some(journal) = self.journal.0.as_mut() {
+			if self.awaiting_input {
+				journal.discard();
+			}
+			else {
+				journal.commit();
+			}
+		}
+	}
+	// record the input state about to be replaced by a line of game input
+	pub(super) fn journal_command(&mut self) {
+		if self.journal.0.is_none() {
+			return;
+		}
+		let entry = CommandEntry {
+			input_buff: self.input_buff.clone(),
+			input_buff_index: self.input_buff_index,
+			awaiting_input: self.awaiting_input,
+			input_ready: self.input_ready,
+		};
+		self.journal.0.as_mut().unwrap().push(JournalEntry::Command(entry));
+	}
+	fn restore_instruction(&mut self, entry:InstructionEntry) {
+		self.mem_ptr = entry.pc;
+		self.reg = entry.reg;
+		let stack_len = self.stack.len().min(entry.stack_len);
+		self.stack.truncate(stack_len);
+		if let Some(val) = entry.popped {
+			self.stack.push(val);
+		}
+		if let Some((addr, old)) = entry.write {
+			self.mem.write(addr, old);
+		}
+		self.restore_frames(entry.frame_pushed, entry.frames_popped);
+		if let Some(buffer) = self.output.buffer_mut() {
+			buffer.truncate(entry.output_len);
+		}
+		self.input_buff_index = entry.input_buff_index;
+		self.awaiting_input = entry.awaiting_input;
+		self.input_ready = entry.input_ready;
+		self.halt = entry.halt;
+	}
+	fn restore_command(&mut self, entry:CommandEntry) {
+		self.input_buff = entry.input_buff;
+		self.input_buff_index = entry.input_buff_index;
+		self.awaiting_input = entry.awaiting_input;
+		self.input_ready = entry.input_ready;
+	}
+	// undo the most recent journal entry. Returns the address written by an
+	// undone instruction and whether the entry was a command.
+	fn undo_entry(&mut self) -> Option<(Option<u16>, bool)> {
+		let entry = self.journal.0.as_mut()?.entries.pop_back()?;
+		// a stop reported before the rewind no longer applies
+		self.watch_hit = None;
+		self.break_resume = None;
+		match entry {
+			JournalEntry::Instruction(entry) => {
+				let write = entry.write.map(|(addr, _)| addr);
+				self.restore_instruction(entry);
+				return Some((write, false));
+			},
+			JournalEntry::Command(entry) => {
+				self.restore_command(entry);
+				return Some((None, true));
+			},
+		}
+	}
+	// undo one instruction. Game input supplied since then is taken back as well.
+	// Returns false if there is nothing left to undo.
+	pub fn step_back(&mut self) -> bool {
+		loop {
+			match self.undo_entry() {
+				Some((_, true)) => continue,
+				Some((_, false)) => return true,
+				None => return false,
+			}
+		}
+	}
+	// undo instructions up to and including the most recent write to addr.
+	// Returns false if the journal runs out first.
+	pub fn run_back_to_write(&mut self, addr:u16) -> bool {
+		loop {
+			match self.undo_entry() {
+				Some((Some(write), false)) if write == addr => return true,
+				Some(_) => continue,
+				None => return false,
+			}
+		}
+	}
+	// undo everything since the last line of game input was supplied, leaving
+	// the vm waiting for that input again. Returns false if the journal runs out first.
+	pub fn undo_command(&mut self) -> bool {
+		loop {
+			match self.undo_entry() {
+				Some((_, true)) => return true,
+				Some(_) => continue,
+				None => return false,
+			}
+		}
+	}
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+const PAGE_BITS: usize = 8;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+pub const MEM_WORDS: usize = 32768;
+const PAGE_COUNT: usize = MEM_WORDS / PAGE_SIZE;
+
+// an instruction as fetched from memory: the opcode and the three words after
+// it. Operands past the end of memory read as 0.
+#[derive(Clone, Copy, Default)]
+pub struct Decoded {
+	pub opcode: u16,
+	pub args: [u16; 3],
+	// the entry holds a decoded instruction
+	cached: bool,
+	// the opcode is one execute_for can run without step(), and its operands
+	// are in memory and name registers where the opcode needs them to
+	pub checked: bool,
+}
+
+impl Decoded {
+	fn check(&mut self, addr:usize) {
+		// operands that must be registers, and operands read as values
+		let (dest, vals) = match self.opcode {
+			// SET, POP, NOT, RMEM
+			1 | 14 | 15 => (1, 1),
+			3 => (1, 0),
+			// EQ, GT, ADD, MULT, MOD, AND, OR
+			4 | 5 | 9..=13 => (1, 2),
+			// PUSH, JMP, CALL, OUT
+			2 | 6 | 17 | 19 => (0, 1),
+			// JT, JF, WMEM
+			7 | 8 | 16 => (0, 2),
+			// RET, NOOP
+			18 | 21 => (0, 0),
+			_ => return,
+		};
+		if addr + dest + vals > super::MEM_MAX as usize {
+			return;
+		}
+		let (dests, vals) = self.args[..dest + vals].split_at(dest);
+		self.checked = dests.iter().all(|&arg| arg > super::LITERAL_MAX && arg <= super::REG_ADDR_MAX)
+			&& vals.iter().all(|&arg| arg <= super::REG_ADDR_MAX);
+	}
+}
+
+// Flat 32K-word address space split into fixed size pages. Pages are reference
+// counted, so cloning the memory only copies the page table. A page is copied
+// the first time either side of a clone writes to it.
+//
+// Decoded instructions are cached per address, in pages allocated the first
+// time an instruction on them is decoded. Cache pages are shared between
+// clones and copied on write like memory pages. A write drops every cached
+// instruction that could include the written word.
+#[derive(Clone)]
+pub struct Memory {
+	pages: Vec<Arc<[u16; PAGE_SIZE]>>,
+	decoded: Vec<Option<Arc<[Decoded; PAGE_SIZE]>>>,
+	decode_cache: bool,
+}
+
+impl Memory {
+	pub fn new() -> Memory {
+		// every page starts out shared with the same zeroed page
+		let zero = Arc::new([0u16; PAGE_SIZE]);
+		Memory { pages: vec![zero; PAGE_COUNT], decoded: vec![None; PAGE_COUNT], decode_cache: true }
+	}
+	pub fn clear(&mut self) {
+		let decode_cache = self.decode_cache;
+		*self = Memory::new();
+		self.decode_cache = decode_cache;
+	}
+	pub fn set_decode_cache(&mut self, enabled:bool) {
+		self.decode_cache = enabled;
+		self.decoded = vec![None; PAGE_COUNT];
+	}
+	pub fn decode_cache(&self) -> bool {
+		return self.decode_cache;
+	}
+	fn fetch(&self, addr:u16) -> Decoded {
+		let mut decoded = Decoded { opcode: self.read(addr), args: [0; 3], cached: false, checked: false };
+		for n in 0..3 {
+			let operand = addr as usize + n + 1;
+			if operand < MEM_WORDS {
+				decoded.args[n] = self.read(operand as u16);
+			}
+		}
+		return decoded;
+	}
+	// the instruction at addr, from the cache if enabled
+	pub fn decode(&mut self, addr:u16) -> Decoded {
+		if !self.decode_cache {
+			return self.fetch(addr);
+		}
+		let addr = addr as usize;
+		if let Some(page) = &self.decoded[addr >> PAGE_BITS] {
+			let entry = page[addr & PAGE_MASK];
+			if entry.cached {
+				return entry;
+			}
+		}
+		let mut decoded = self.fetch(addr as u16);
+		decoded.cached = true;
+		decoded.check(addr);
+		let page = self.decoded[addr >> PAGE_BITS].get_or_insert_with(|| Arc::new([Decoded::default(); PAGE_SIZE]));
+		Arc::make_mut(page)[addr & PAGE_MASK] = decoded;
+		return decoded;
+	}
+	fn invalidate(&mut self, addr:usize) {
+		// an instruction is at most four words long, and may start on the page before
+		for index in addr.saturating_sub(3)..=addr {
+			if let Some(page) = &mut self.decoded[index >> PAGE_BITS] {
+				// leave pages without the entry cached shared
+				if page[index & PAGE_MASK].cached {
+					Arc::make_mut(page)[index & PAGE_MASK].cached = false;
+				}
+			}
+		}
+	}
+	// callers are expected to have range checked addr against the address space
+	pub fn read(&self, addr:u16) -> u16 {
+		let addr = addr as usize;
+		return self.pages[addr >> PAGE_BITS][addr & PAGE_MASK];
+	}
+	pub fn write(&mut self, addr:u16, val:u16) {
+		let addr = addr as usize;
+		let page = &mut self.pages[addr >> PAGE_BITS];
+		// skip the copy on write if the value is unchanged
+		if page[addr & PAGE_MASK] == val {
+			return;
+		}
+		Arc::make_mut(page)[addr & PAGE_MASK] = val;
+		self.invalidate(addr);
+	}
+	// the page holding addr is the same allocation in both, not just equal
+	pub(super) fn shares_page(&self, other:&Memory, addr:u16) -> bool {
+		let page = addr as usize >> PAGE_BITS;
+		return Arc::ptr_eq(&self.pages[page], &other.pages[page]);
+	}
+	// (addr, self, other) for every word that differs. Pages still shared
+	// between the two are skipped without comparing them.
+	pub fn diff(&self, other:&Memory) -> Vec<(u16, u16, u16)> {
+		let mut changes = Vec::new();
+		for base in (0..MEM_WORDS).step_by(PAGE_SIZE) {
+			if self.shares_page(other, base as u16) {
+				continue;
+			}
+			for addr in base..base + PAGE_SIZE {
+				let (a, b) = (self.read(addr as u16), other.read(addr as u16));
+				if a != b {
+					changes.push((addr as u16, a, b));
+				}
+			}
+		}
+		return changes;
+	}
+	pub fn load(&mut self, input:&[u16]) {
+		self.clear();
+		for (page_index, chunk) in input.chunks(PAGE_SIZE).enumerate() {
+			let mut page = [0u16; PAGE_SIZE];
+			page[..chunk.len()].copy_from_slice(chunk);
+			self.pages[page_index] = Arc::new(page);
+		}
+	}
+}
@@ -0,0 +1,271 @@
+// Save-state file format, version 1. All values are little-endian.
+//
+//	offset	size	field
+//	0		4		magic "SYNS"
+//	4		2		format version
+//	6		2		flags: bit 0 awaiting input, bit 1 input ready
+//	8		2		mem_ptr
+//	10		16		registers r0-r7
+//	26		65536	memory, 32768 words
+//	65562	...		stack: u32 length then one u16 per entry, bottom first
+//			...		input buffer: u32 length, one u32 code point per char, then u32 read index
+//			...		output buffer: u32 length, one u32 code point per char
+//			8		halt state: u16 tag, u16 addr, u16 opcode, u16 extra (see encode_halt)
+//			8		number of instructions executed
+//			4		CRC-32 (IEEE) of every preceding byte
+use super::{SynacorVM, HaltReason, VMError, REG_N};
+use super::memory::MEM_WORDS;
+use std::fmt;
+use std::fs;
+use std::io;
+
+const MAGIC: &[u8; 4] = b"SYNS";
+pub const SAVE_STATE_VERSION: u16 = 1;
+const FLAG_AWAITING_INPUT: u16 = 1;
+const FLAG_INPUT_READY: u16 = 2;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+	Io(io::Error),
+	BadMagic,
+	UnsupportedVersion(u16),
+	Truncated,
+	ChecksumMismatch { stored:u32, computed:u32 },
+	InvalidData(String),
+}
+
+impl fmt::Display for SaveStateError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SaveStateError::Io(err) => write!(f, "I/O error: {}", err),
+			SaveStateError::BadMagic => write!(f, "not a save-state file"),
+			SaveStateError::UnsupportedVersion(v) => write!(f, "unsupported save-state version {} (expected {})", v, SAVE_STATE_VERSION),
+			SaveStateError::Truncated => write!(f, "save-state file is truncated"),
+			SaveStateError::ChecksumMismatch { stored, computed } => write!(f, "checksum mismatch (stored {:08x}, computed {:08x})", stored, computed),
+			SaveStateError::InvalidData(msg) => write!(f, "invalid save-state data: {}", msg),
+		}
+	}
+}
+
+impl From<io::Error> for SaveStateError {
+	fn from(err: io::Error) -> SaveStateError {
+		return SaveStateError::Io(err);
+	}
+}
+
+pub(super) fn crc32(data:&[u8]) -> u32 {
+	let mut crc:u32 = 0xffffffff;
+	for byte in data {
+		crc ^= *byte as u32;
+		for _ in 0..8 {
+			let mask = (!(crc & 1)).wrapping_add(1);
+			crc = (crc >> 1) ^ (0xedb88320 & mask);
+		}
+	}
+	return !crc;
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, n:usize) -> Result<&'a [u8], SaveStateError> {
+		if self.pos + n > self.data.len() {
+			return Err(SaveStateError::Truncated);
+		}
+		let slice = &self.data[self.pos..self.pos + n];
+		self.pos += n;
+		return Ok(slice);
+	}
+	fn u16(&mut self) -> Result<u16, SaveStateError> {
+		let b = self.bytes(2)?;
+		return Ok(u16::from_le_bytes([b[0], b[1]]));
+	}
+	fn u32(&mut self) -> Result<u32, SaveStateError> {
+		let b = self.bytes(4)?;
+		return Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+	}
+	fn u64(&mut self) -> Result<u64, SaveStateError> {
+		let b = self.bytes(8)?;
+		return Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]));
+	}
+	fn chars(&mut self) -> Result<Vec<char>, SaveStateError> {
+		let len = self.u32()? as usize;
+		let mut chars = Vec::new();
+		for _ in 0..len {
+			let code = self.u32()?;
+			match std::char::from_u32(code) {
+				Some(c) => chars.push(c),
+				None => return Err(SaveStateError::InvalidData(format!("invalid character code {}", code))),
+			}
+		}
+		return Ok(chars);
+	}
+}
+
+fn push_u16(out:&mut Vec<u8>, val:u16) {
+	out.extend_from_slice(&val.to_le_bytes());
+}
+fn push_u32(out:&mut Vec<u8>, val:u32) {
+	out.extend_from_slice(&val.to_le_bytes());
+}
+fn push_u64(out:&mut Vec<u8>, val:u64) {
+	out.extend_from_slice(&val.to_le_bytes());
+}
+fn push_chars(out:&mut Vec<u8>, chars:&[char]) {
+	push_u32(out, chars.len() as u32);
+	for c in chars {
+		push_u32(out, *c as u32);
+	}
+}
+
+// tag 0 means the vm is still running. extra holds the operand for
+// InvalidOperand, the target for MemoryOutOfRange and the character for NonAscii.
+fn encode_halt(halt:&Option<HaltReason>) -> [u16; 4] {
+	return match halt {
+		None => [0, 0, 0, 0],
+		Some(HaltReason::CleanHalt { addr, opcode }) => [1, *addr, *opcode, 0],
+		Some(HaltReason::Fault(err)) => match err {
+			VMError::InvalidOperand { addr, opcode, operand } => [2, *addr, *opcode, *operand],
+			VMError::StackUnderflow { addr, opcode } => [3, *addr, *opcode, 0],
+			VMError::UndefinedOpcode { addr, opcode } => [4, *addr, *opcode, 0],
+			VMError::MemoryOutOfRange { addr, opcode, target } => [5, *addr, *opcode, *target],
+			VMError::DivisionByZero { addr, opcode } => [6, *addr, *opcode, 0],
+			VMError::InvalidProgramCounter { addr } => [7, *addr, 0, 0],
+			VMError::NonAscii { addr, opcode, val } => [8, *addr, *opcode, *val],
+		},
+	};
+}
+fn decode_halt(fields:[u16; 4]) -> Result<Option<HaltReason>, SaveStateError> {
+	let [tag, addr, opcode, extra] = fields;
+	let err = match tag {
+		0 => return Ok(None),
+		1 => return Ok(Some(HaltReason::CleanHalt { addr, opcode })),
+		2 => VMError::InvalidOperand { addr, opcode, operand: extra },
+		3 => VMError::StackUnderflow { addr, opcode },
+		4 => VMError::UndefinedOpcode { addr, opcode },
+		5 => VMError::MemoryOutOfRange { addr, opcode, target: extra },
+		6 => VMError::DivisionByZero { addr, opcode },
+		7 => VMError::InvalidProgramCounter { addr },
+		8 => VMError::NonAscii { addr, opcode, val: extra },
+		_ => return Err(SaveStateError::InvalidData(format!("unknown halt tag {}", tag))),
+	};
+	return Ok(Some(HaltReason::Fault(err)));
+}
+
+impl SynacorVM {
+	// serialize the machine state. The interactive flag and the I/O backends are
+	// properties of the session rather than the machine and are not saved, apart
+	// from output held in an output buffer. Call frames are not saved either, so
+	// backtraces after loading only show calls made since.
+	pub fn save_state(&self) -> Vec<u8> {
+		let mut out:Vec<u8> = Vec::new();
+		out.extend_from_slice(MAGIC);
+		push_u16(&mut out, SAVE_STATE_VERSION);
+		let mut flags = 0;
+		if self.awaiting_input {
+			flags |= FLAG_AWAITING_INPUT;
+		}
+		if self.input_ready {
+			flags |= FLAG_INPUT_READY;
+		}
+		push_u16(&mut out, flags);
+		push_u16(&mut out, self.mem_ptr);
+		for r in self.reg.iter() {
+			push_u16(&mut out, *r);
+		}
+		for addr in 0..MEM_WORDS {
+			push_u16(&mut out, self.mem.read(addr as u16));
+		}
+		push_u32(&mut out, self.stack.len() as u32);
+		for val in self.stack.iter() {
+			push_u16(&mut out, *val);
+		}
+		push_chars(&mut out, &self.input_buff);
+		push_u32(&mut out, self.input_buff_index as u32);
+		let output_buff:Vec<char> = self.output.buffer().cloned().unwrap_or_default();
+		push_chars(&mut out, &output_buff);
+		for field in encode_halt(&self.halt).iter() {
+			push_u16(&mut out, *field);
+		}
+		push_u64(&mut out, self.executed);
+		let checksum = crc32(&out);
+		push_u32(&mut out, checksum);
+		return out;
+	}
+	// replace the machine state with a previously saved one. The vm is left
+	// untouched if the data fails to validate.
+	pub fn load_state(&mut self, data:&[u8]) -> Result<(), SaveStateError> {
+		if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+			return Err(SaveStateError::BadMagic);
+		}
+		if data.len() < MAGIC.len() + 2 + 4 {
+			return Err(SaveStateError::Truncated);
+		}
+		let (body, checksum_bytes) = data.split_at(data.len() - 4);
+		let stored = u32::from_le_bytes([checksum_bytes[0], checksum_bytes[1], checksum_bytes[2], checksum_bytes[3]]);
+		let mut reader = Reader { data: body, pos: MAGIC.len() };
+		// check the version before the checksum so a file from a newer build gets a useful error
+		let version = reader.u16()?;
+		if version != SAVE_STATE_VERSION {
+			return Err(SaveStateError::UnsupportedVersion(version));
+		}
+		let computed = crc32(body);
+		if stored != computed {
+			return Err(SaveStateError::ChecksumMismatch { stored, computed });
+		}
+		let flags = reader.u16()?;
+		let mem_ptr = reader.u16()?;
+		let mut reg = [0u16; REG_N];
+		for r in reg.iter_mut() {
+			*r = reader.u16()?;
+		}
+		let mut mem:Vec<u16> = Vec::with_capacity(MEM_WORDS);
+		for _ in 0..MEM_WORDS {
+			mem.push(reader.u16()?);
+		}
+		let stack_len = reader.u32()? as usize;
+		let mut stack:Vec<u16> = Vec::new();
+		for _ in 0..stack_len {
+			stack.push(reader.u16()?);
+		}
+		let input_buff = reader.chars()?;
+		let input_buff_index = reader.u32()? as usize;
+		let output_buff = reader.chars()?;
+		let halt = decode_halt([reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?])?;
+		let executed = reader.u64()?;
+		if reader.pos != body.len() {
+			return Err(SaveStateError::InvalidData(format!("{} unexpected trailing bytes", body.len() - reader.pos)));
+		}
+		if input_buff_index > input_buff.len() {
+			return Err(SaveStateError::InvalidData(format!("input index {} past end of input buffer", input_buff_index)));
+		}
+
+		self.mem.load(&mem);
+		self.reg = reg;
+		self.stack = stack;
+		self.mem_ptr = mem_ptr;
+		self.input_buff = input_buff;
+		self.input_buff_index = input_buff_index;
+		if let Some(buffer) = self.output.buffer_mut() {
+			*buffer = output_buff;
+		}
+		self.awaiting_input = flags & FLAG_AWAITING_INPUT != 0;
+		self.input_ready = flags & FLAG_INPUT_READY != 0;
+		self.halt = halt;
+		self.executed = executed;
+		self.clear_frames();
+		self.clear_journal();
+		return Ok(());
+	}
+	pub fn save_state_file(&self, path:&str) -> Result<(), SaveStateError> {
+		fs::write(path, self.save_state())?;
+		return Ok(());
+	}
+	pub fn load_state_file(&mut self, path:&str) -> Result<(), SaveStateError> {
+		let data = fs::read(path)?;
+		return self.load_state(&data);
+	}
+}
@@ -0,0 +1,651 @@
+use super::*;
+
+const R0: u16 = 32768;
+const R1: u16 = 32769;
+
+fn load(prog:&[u16], strictness:Strictness) -> SynacorVM {
+	let mut vm = SynacorVM::new(false);
+	vm.set_strictness(strictness);
+	vm.load_mem(prog.to_vec()).unwrap();
+	return vm;
+}
+fn run(prog:&[u16], strictness:Strictness) -> SynacorVM {
+	let mut vm = load(prog, strictness);
+	vm.execute_for(1000);
+	return vm;
+}
+// run in both modes, for behaviour that does not depend on strictness
+fn run_both(prog:&[u16]) -> Vec<SynacorVM> {
+	return vec![run(prog, Strictness::Strict), run(prog, Strictness::Lenient)];
+}
+fn fault(vm:&SynacorVM) -> Option<VMError> {
+	return match vm.halt_reason() {
+		Some(HaltReason::Fault(err)) => Some(err.clone()),
+		_ => None,
+	};
+}
+fn clean_halt_at(vm:&SynacorVM) -> Option<u16> {
+	return match vm.halt_reason() {
+		Some(HaltReason::CleanHalt { addr, .. }) => Some(*addr),
+		_ => None,
+	};
+}
+
+#[test]
+fn pop_empty_stack() {
+	let strict = run(&[3, R0, 0], Strictness::Strict);
+	assert_eq!(fault(&strict), Some(VMError::StackUnderflow { addr: 0, opcode: 3 }));
+	let lenient = run(&[3, R0, 0], Strictness::Lenient);
+	assert_eq!(lenient.halt_reason(), Some(&HaltReason::CleanHalt { addr: 0, opcode: 3 }));
+}
+
+#[test]
+fn binary_op_literal_destination() {
+	for vm in run_both(&[9, 5, 1, 2, 0]) {
+		assert_eq!(fault(&vm), Some(VMError::InvalidOperand { addr: 0, opcode: 9, operand: 5 }));
+		assert_eq!(vm.get_mem(5), Some(0));
+	}
+	// MOD checks its destination before the divisor, like the other operations
+	for vm in run_both(&[11, 5, 7, 0, 0]) {
+		assert_eq!(fault(&vm), Some(VMError::InvalidOperand { addr: 0, opcode: 11, operand: 5 }));
+	}
+}
+
+#[test]
+fn out_non_ascii() {
+	let strict = run(&[19, 200, 0], Strictness::Strict);
+	assert_eq!(fault(&strict), Some(VMError::NonAscii { addr: 0, opcode: 19, val: 200 }));
+	let mut lenient = run(&[19, 321, 0], Strictness::Lenient);
+	// truncated to 8 bits
+	assert_eq!(lenient.output_line(true), "A");
+}
+
+#[test]
+fn in_waits_for_input() {
+	for strictness in [Strictness::Strict, Strictness::Lenient] {
+		let mut vm = load(&[20, R0, 20, R1, 0], strictness);
+		assert_eq!(vm.execute(), StopReason::AwaitingInput);
+		assert_eq!(vm.get_mem_ptr(), 0);
+		vm.set_input_line("h");
+		vm.execute();
+		assert_eq!(vm.get_register(0), 'h' as u16);
+		assert_eq!(vm.get_register(1), '\n' as u16);
+		assert_eq!(clean_halt_at(&vm), Some(4));
+	}
+}
+
+#[test]
+fn in_non_ascii() {
+	let mut strict = load(&[20, R0, 0], Strictness::Strict);
+	strict.execute();
+	strict.set_input_line("\u{e9}");
+	strict.execute();
+	assert_eq!(fault(&strict), Some(VMError::NonAscii { addr: 0, opcode: 20, val: 0xe9 }));
+	let mut lenient = load(&[20, R0, 0], Strictness::Lenient);
+	lenient.execute();
+	lenient.set_input_line("\u{10000}");
+	lenient.execute();
+	// masked to 15 bits
+	assert_eq!(lenient.get_register(0), 0);
+}
+
+#[test]
+fn clones_do_not_see_each_others_writes() {
+	let original = load(&[9, R0, 1, 2, 0], Strictness::Lenient);
+	let mut clone = original.clone();
+	clone.set_mem(1, 7);
+	clone.set_mem(20000, 3);
+	assert_eq!(original.get_mem(1), Some(R0));
+	assert_eq!(original.get_mem(20000), Some(0));
+	let mut original = original;
+	original.set_mem(2, 5);
+	assert_eq!(clone.get_mem(2), Some(1));
+	assert_eq!(clone.get_mem(1), Some(7));
+}
+
+#[test]
+fn clones_share_pages_until_written() {
+	let original = load(&[9, R0, 1, 2, 0], Strictness::Lenient);
+	let mut clone = original.clone();
+	assert!(clone.mem.shares_page(&original.mem, 0));
+	assert!(clone.mem.shares_page(&original.mem, 30000));
+	// writing the value already there does not copy the page
+	clone.set_mem(1, R0);
+	assert!(clone.mem.shares_page(&original.mem, 1));
+	clone.set_mem(1000, 4);
+	assert!(!clone.mem.shares_page(&original.mem, 1000));
+	assert!(clone.mem.shares_page(&original.mem, 0));
+	assert!(clone.mem.shares_page(&original.mem, 30000));
+}
+
+#[test]
+fn decode_cache_sees_self_modifying_code() {
+	// OUT 65 runs once, is rewritten to OUT 66 and runs again
+	let prog = [19, 65, 7, R0, 13, 1, R0, 1, 16, 1, 66, 6, 0, 0];
+	for cache in [true, false] {
+		let mut vm = load(&prog, Strictness::Lenient);
+		vm.set_decode_cache(cache);
+		vm.execute_for(1000);
+		assert_eq!(clean_halt_at(&vm), Some(13));
+		assert_eq!(vm.output_line(true), "AB");
+	}
+}
+
+#[test]
+fn decode_cache_is_copied_on_write_between_clones() {
+	// OUT 65; JMP 0
+	let mut vm = load(&[19, 65, 6, 0], Strictness::Lenient);
+	vm.execute_for(2);
+	let mut clone = vm.clone();
+	clone.set_mem(1, 66);
+	vm.execute_for(2);
+	clone.execute_for(2);
+	assert_eq!(vm.output_line(true), "AA");
+	assert_eq!(clone.output_line(true), "AB");
+}
+
+#[test]
+fn decode_cache_drops_instructions_across_pages() {
+	// JMP 254; ...; 254: ADD r0 1 2; JMP 254. The ADD ends on the next page.
+	let mut prog = vec![0; 260];
+	prog[..2].copy_from_slice(&[6, 254]);
+	prog[254..260].copy_from_slice(&[9, R0, 1, 2, 6, 254]);
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.execute_for(2);
+	assert_eq!(vm.get_register(0), 3);
+	vm.set_mem(257, 5);
+	vm.execute_for(2);
+	assert_eq!(vm.get_register(0), 6);
+}
+
+#[test]
+fn checked_instructions_run_like_step() {
+	// SET r0 5; CALL 20; ADD r0 r0 -1; JT r0 3; OUT '\n'; POP r1 on an empty stack
+	// 20: WMEM 100 r0; RMEM r1 100; OUT 'A'; PUSH r1; POP r2; RET
+	let mut prog = vec![1, R0, 5, 17, 20, 9, R0, R0, 32767, 7, R0, 3, 19, 10, 3, R1, 0, 0, 0, 0];
+	prog.extend_from_slice(&[16, 100, R0, 15, R1, 100, 19, 65, 2, R1, 3, R0 + 2, 18]);
+	for strictness in [Strictness::Strict, Strictness::Lenient] {
+		let mut vms = Vec::new();
+		for decode_cache in [true, false] {
+			let mut vm = load(&prog, strictness);
+			vm.set_decode_cache(decode_cache);
+			vm.execute_for(1000);
+			vms.push(vm);
+		}
+		let (mut checked, mut stepped) = (vms.remove(0), vms.remove(0));
+		assert_eq!(checked.get_mem(100), Some(1));
+		assert_eq!(checked.output_line(true), "AAAAA\n");
+		assert_eq!(stepped.output_line(true), "AAAAA\n");
+		assert!(checked.halt_reason().is_some());
+		assert_eq!(checked.halt_reason(), stepped.halt_reason());
+		assert_eq!(checked.instruction_count(), stepped.instruction_count());
+		assert_eq!(checked.get_mem_ptr(), stepped.get_mem_ptr());
+		assert_eq!(checked.get_stack(), stepped.get_stack());
+		assert_eq!(checked.call_graph().max_call_depth(), stepped.call_graph().max_call_depth());
+		assert_eq!((0..8).map(|r| checked.get_register(r)).collect::<Vec<u16>>(), (0..8).map(|r| stepped.get_register(r)).collect::<Vec<u16>>());
+	}
+}
+
+#[test]
+fn diff_lists_changes() {
+	let before = load(&[1, R1, 7, 16, 100, 42, 2, 9, 0], Strictness::Lenient);
+	let mut after = before.clone();
+	after.execute_for(1000);
+	let diff = before.diff(&after);
+	assert_eq!(diff.pc, Some((0, 9)));
+	assert_eq!(diff.registers, vec![(1, 0, 7)]);
+	assert_eq!(diff.memory, vec![(100, 0, 42)]);
+	assert_eq!(diff.stack, vec![(0, None, Some(9))]);
+	assert!(after.diff(&after.clone()).is_empty());
+}
+
+#[test]
+fn observers_see_events() {
+	let prog = [17, 6, 20, R0, 0, 0, 19, 72, 19, 10, 16, 100, 5, 18];
+	let mut vm = load(&prog, Strictness::Lenient);
+	let (tx, rx) = std::sync::mpsc::channel();
+	vm.subscribe(Box::new(tx));
+	assert_eq!(vm.execute(), StopReason::AwaitingInput);
+	vm.set_input_line("x");
+	vm.execute();
+	let events:Vec<Event> = rx.try_iter().collect();
+	assert_eq!(events, vec![
+		Event::Call { call_site: 0, entry: 6 },
+		Event::OutputChar('H'),
+		Event::OutputChar('\n'),
+		Event::OutputLine("H".to_string()),
+		Event::MemoryWrite { pc: 10, addr: 100, old: 0, new: 5 },
+		Event::Return { pc: 13, addr: 2 },
+		Event::InputRequested { pc: 2 },
+		Event::InputConsumed("x".to_string()),
+		Event::Halted(HaltReason::CleanHalt { addr: 4, opcode: 0 }),
+	]);
+}
+
+// a vm part way through the program: output printed, a value on the stack and
+// waiting for input
+fn saved_vm() -> SynacorVM {
+	let prog = [19, 72, 2, 1234, 1, R1, 9, 20, R0, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	assert_eq!(vm.execute(), StopReason::AwaitingInput);
+	return vm;
+}
+
+#[test]
+fn save_state_round_trips() {
+	let vm = saved_vm();
+	let data = vm.save_state();
+	let mut loaded = SynacorVM::new(false);
+	loaded.load_state(&data).unwrap();
+	assert_eq!(loaded.save_state(), data);
+	assert_eq!(loaded.get_mem_ptr(), vm.get_mem_ptr());
+	assert_eq!(loaded.get_register(1), 9);
+	assert_eq!(loaded.get_stack(), &[1234]);
+	assert_eq!(loaded.output_line(false), "H");
+	assert_eq!(loaded.instruction_count(), 3);
+	assert!(loaded.is_awaiting_input());
+	loaded.set_input_line("A");
+	loaded.execute();
+	assert_eq!(loaded.get_register(0), 65);
+	assert!(clean_halt_at(&loaded).is_some());
+}
+
+#[test]
+fn load_state_rejects_bad_files() {
+	let data = saved_vm().save_state();
+	let mut vm = load(&[21, 0], Strictness::Lenient);
+	let before = vm.save_state();
+
+	let mut corrupt = data.clone();
+	corrupt[100] ^= 1;
+	assert!(matches!(vm.load_state(&corrupt), Err(SaveStateError::ChecksumMismatch { .. })));
+
+	let mut newer = data.clone();
+	newer[4..6].copy_from_slice(&(SAVE_STATE_VERSION + 1).to_le_bytes());
+	assert!(matches!(vm.load_state(&newer), Err(SaveStateError::UnsupportedVersion(v)) if v == SAVE_STATE_VERSION + 1));
+
+	assert!(matches!(vm.load_state(b"NOPE12345678"), Err(SaveStateError::BadMagic)));
+	assert!(matches!(vm.load_state(&data[..8]), Err(SaveStateError::Truncated)));
+	// cut off part way through memory, with a checksum that matches what is left
+	let mut truncated = data[..1000].to_vec();
+	let checksum = save_state::crc32(&truncated);
+	truncated.extend_from_slice(&checksum.to_le_bytes());
+	assert!(matches!(vm.load_state(&truncated), Err(SaveStateError::Truncated)));
+
+	// a failed load leaves the vm as it was
+	assert_eq!(vm.save_state(), before);
+}
+
+#[test]
+fn breakpoint_stops_before_the_instruction_and_resumes() {
+	let prog = [1, R0, 1, 16, 100, 5, 15, R1, 100, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.breakpoints_mut().add_pc(3);
+	assert_eq!(vm.execute(), StopReason::Breakpoint { addr: 3 });
+	assert_eq!(vm.get_mem_ptr(), 3);
+	assert_eq!(vm.get_mem(100), Some(0));
+	// resuming runs the instruction at the breakpoint instead of stopping again
+	assert!(matches!(vm.execute(), StopReason::Halted(_)));
+	assert_eq!(vm.get_register(1), 5);
+}
+
+#[test]
+fn watchpoints_stop_after_the_access() {
+	let prog = [1, R0, 1, 16, 100, 5, 15, R1, 100, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.breakpoints_mut().add_write(100);
+	vm.breakpoints_mut().add_read(100);
+	assert!(vm.breakpoints_mut().add_register(1));
+	assert_eq!(vm.execute(), StopReason::MemoryWrite { pc: 3, addr: 100, old: 0, new: 5 });
+	assert_eq!(vm.get_mem_ptr(), 6);
+	// RMEM both reads the watched address and writes the watched register; the last is reported
+	assert_eq!(vm.execute(), StopReason::RegisterWrite { pc: 6, reg: 1, old: 0, new: 5 });
+	assert!(vm.breakpoints_mut().remove_register(1));
+	vm.set_mem_ptr(6);
+	assert_eq!(vm.execute(), StopReason::MemoryRead { pc: 6, addr: 100, val: 5 });
+	assert!(matches!(vm.execute(), StopReason::Halted(_)));
+}
+
+#[test]
+fn register_watchpoints_need_a_register() {
+	let mut breakpoints = Breakpoints::default();
+	assert!(!breakpoints.add_register(8));
+	assert!(!breakpoints.remove_register(8));
+	assert!(breakpoints.add_register(7));
+}
+
+#[test]
+fn execute_once_reports_why_nothing_ran() {
+	let mut vm = load(&[20, R0, 0], Strictness::Lenient);
+	assert_eq!(vm.execute_once(), None);
+	assert_eq!(vm.execute_once(), Some(StopReason::AwaitingInput));
+	assert_eq!(vm.get_mem_ptr(), 0);
+	vm.set_input_line("a");
+	while vm.execute_once().is_none() {}
+	assert_eq!(vm.execute_once(), Some(StopReason::Halted(HaltReason::CleanHalt { addr: 2, opcode: 0 })));
+}
+
+#[test]
+fn execute_for_stops_an_infinite_loop() {
+	// JMP 0
+	let mut vm = load(&[6, 0], Strictness::Lenient);
+	assert_eq!(vm.execute_for(500), StopReason::BudgetExhausted);
+	assert_eq!(vm.instruction_count(), 500);
+	assert_eq!(vm.halt_reason(), None);
+}
+
+#[test]
+fn run_until_stops_at_the_address() {
+	let mut vm = load(&[21, 21, 21, 21, 0], Strictness::Lenient);
+	assert_eq!(vm.run_until(|vm| vm.get_mem_ptr() == 3), StopReason::Condition);
+	assert_eq!(vm.get_mem_ptr(), 3);
+	assert_eq!(vm.instruction_count(), 3);
+}
+
+#[test]
+fn input_line_for_reports_how_the_command_ended() {
+	// IN r0; OUT r0; JMP 0 echoes the line and asks for another
+	let mut echo = load(&[20, R0, 19, R0, 6, 0], Strictness::Lenient);
+	assert_eq!(echo.execute(), StopReason::AwaitingInput);
+	assert_eq!(echo.input_line_for("hi", 1000), StopReason::AwaitingInput);
+	assert_eq!(echo.output_line(true), "hi\n");
+	// IN r0; JMP 2 never asks again
+	let mut busy = load(&[20, R0, 6, 2], Strictness::Lenient);
+	assert_eq!(busy.execute(), StopReason::AwaitingInput);
+	assert_eq!(busy.input_line_for("hi", 1000), StopReason::BudgetExhausted);
+}
+
+// a trace destination the test can read back after stop_trace()
+#[derive(Clone, Default)]
+struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+	fn write(&mut self, buf:&[u8]) -> io::Result<usize> {
+		self.0.lock().unwrap().extend_from_slice(buf);
+		return Ok(buf.len());
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		return Ok(());
+	}
+}
+
+fn trace_lines(config:TraceConfig) -> Vec<String> {
+	let prog = [19, 97, 20, R0, 19, 98, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	let buffer = SharedBuffer::default();
+	vm.start_trace(Box::new(buffer.clone()), config).unwrap();
+	assert_eq!(vm.execute(), StopReason::AwaitingInput);
+	vm.set_input_line("x");
+	vm.execute();
+	vm.stop_trace().unwrap();
+	let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+	return text.lines().map(|line| line.to_string()).collect();
+}
+
+// the sequence number, address and instruction of each trace line
+fn trace_heads(config:TraceConfig) -> Vec<String> {
+	return trace_lines(config).iter().map(|line| line.split(" [").next().unwrap().to_string()).collect();
+}
+
+#[test]
+fn trace_numbers_completed_instructions() {
+	// IN waiting for input is numbered once, when it completes
+	assert_eq!(trace_heads(TraceConfig::default()), vec!["0 #0 OUT 97", "1 #2 IN reg0", "2 #4 OUT 98", "3 #6 HALT"]);
+	assert!(trace_lines(TraceConfig::default())[1].ends_with(" r0:0->120"));
+}
+
+#[test]
+fn trace_filters_keep_the_numbering() {
+	let range = TraceConfig { range: Some((4, 6)), ..TraceConfig::default() };
+	assert_eq!(trace_heads(range), vec!["2 #4 OUT 98", "3 #6 HALT"]);
+	let skip_and_limit = TraceConfig { skip: 1, limit: Some(2), ..TraceConfig::default() };
+	assert_eq!(trace_heads(skip_and_limit), vec!["1 #2 IN reg0", "2 #4 OUT 98"]);
+}
+
+#[test]
+fn step_back_undoes_one_instruction_at_a_time() {
+	// SET r0 5; PUSH r0; WMEM 100 7; POP r1; HALT
+	let prog = [1, R0, 5, 2, R0, 16, 100, 7, 3, R1, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.enable_journal(100);
+	vm.execute();
+	assert!(vm.is_halted());
+	assert_eq!(vm.journal_len(), 5);
+	assert!(vm.step_back());
+	assert!(!vm.is_halted());
+	assert_eq!(vm.get_mem_ptr(), 10);
+	assert!(vm.step_back());
+	assert_eq!((vm.get_register(1), vm.get_stack()), (0, &[5][..]));
+	assert!(vm.step_back());
+	assert_eq!(vm.get_mem(100), Some(0));
+	assert!(vm.step_back());
+	assert!(vm.get_stack().is_empty());
+	assert!(vm.step_back());
+	assert_eq!((vm.get_mem_ptr(), vm.get_register(0)), (0, 0));
+	assert!(!vm.step_back());
+	// running forward again gives the same result
+	vm.execute();
+	assert_eq!((vm.get_register(1), vm.get_mem(100)), (5, Some(7)));
+}
+
+#[test]
+fn run_back_to_write_stops_before_the_write() {
+	// WMEM 100 1; WMEM 101 2; WMEM 100 3; HALT
+	let prog = [16, 100, 1, 16, 101, 2, 16, 100, 3, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.enable_journal(100);
+	vm.execute();
+	assert!(vm.run_back_to_write(100));
+	assert_eq!((vm.get_mem_ptr(), vm.get_mem(100), vm.get_mem(101)), (6, Some(1), Some(2)));
+	assert!(vm.run_back_to_write(100));
+	assert_eq!((vm.get_mem_ptr(), vm.get_mem(100), vm.get_mem(101)), (0, Some(0), Some(0)));
+	assert!(!vm.run_back_to_write(101));
+}
+
+#[test]
+fn undo_command_takes_back_the_input() {
+	// IN r0; IN r1; HALT
+	let prog = [20, R0, 20, R1, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.enable_journal(100);
+	assert_eq!(vm.execute(), StopReason::AwaitingInput);
+	// the newline ending the line feeds the second IN
+	vm.set_input_line("a");
+	assert!(matches!(vm.execute(), StopReason::Halted(_)));
+	assert_eq!((vm.get_register(0), vm.get_register(1)), (97, 10));
+	assert!(vm.undo_command());
+	assert!(vm.is_awaiting_input());
+	assert_eq!((vm.get_mem_ptr(), vm.get_register(0), vm.get_register(1)), (0, 0, 0));
+	vm.set_input_line("b");
+	vm.execute();
+	assert_eq!(vm.get_register(0), 98);
+}
+
+#[test]
+fn loading_a_state_clears_the_journal() {
+	let saved = saved_vm().save_state();
+	// SET r0 17; WMEM 100 16; HALT
+	let prog = [1, R0, 17, 16, 100, 16, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.enable_journal(100);
+	vm.execute();
+	assert_eq!(vm.journal_len(), 3);
+	vm.load_state(&saved).unwrap();
+	assert_eq!(vm.journal_len(), 0);
+	assert!(!vm.step_back());
+	assert_eq!(vm.get_register(0), 0);
+	// the journal stays enabled for what runs after the load
+	vm.set_input_line("A");
+	vm.execute();
+	assert!(vm.journal_len() > 0);
+	vm.load_mem(prog.to_vec()).unwrap();
+	assert_eq!(vm.journal_len(), 0);
+}
+
+#[test]
+fn hook_return_replaces_the_function() {
+	// CALL 4; HALT; data; SET r0 9; RET
+	let mut vm = load(&[17, 4, 0, 0, 1, R0, 9, 18], Strictness::Lenient);
+	vm.register_hook(4, |state| {
+		assert_eq!(state.stack(), &[2]);
+		state.set_register(0, 5);
+		return HookAction::Return;
+	});
+	vm.execute();
+	assert_eq!(vm.get_register(0), 5);
+	assert_eq!(clean_halt_at(&vm), Some(2));
+	assert_eq!(vm.get_stack().len(), 0);
+}
+
+#[test]
+fn hook_return_on_empty_stack_halts_like_ret() {
+	let mut vm = load(&[1, R0, 9, 0], Strictness::Lenient);
+	vm.register_hook(0, |_| HookAction::Return);
+	vm.execute();
+	assert_eq!(vm.halt_reason(), Some(&HaltReason::CleanHalt { addr: 0, opcode: 18 }));
+	assert_eq!(vm.get_register(0), 0);
+}
+
+#[test]
+fn hooks_skip_jump_and_continue() {
+	// SET r0 9; HALT; SET r1 7; HALT
+	let prog = [1, R0, 9, 0, 1, R1, 7, 0];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.register_hook(0, |_| HookAction::Skip);
+	vm.execute();
+	assert_eq!((vm.get_register(0), clean_halt_at(&vm)), (0, Some(3)));
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.register_hook(0, |_| HookAction::Jump(4));
+	vm.execute();
+	assert_eq!((vm.get_register(0), vm.get_register(1), clean_halt_at(&vm)), (0, 7, Some(7)));
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.register_hook(0, |state| {
+		state.set_register(1, 3);
+		return HookAction::Continue;
+	});
+	vm.execute();
+	assert_eq!((vm.get_register(0), vm.get_register(1), clean_halt_at(&vm)), (9, 3, Some(3)));
+	assert_eq!(vm.hook_addrs(), vec![0]);
+	assert!(vm.remove_hook(0));
+	assert!(!vm.remove_hook(0));
+}
+
+fn profile_of(vm:&SynacorVM, entry:u16) -> FunctionProfile {
+	return vm.profile().unwrap().function_counts().into_iter().find(|function| function.entry == entry).unwrap();
+}
+
+#[test]
+fn profile_counts_addresses_and_opcodes() {
+	// SET r0 2; ADD r0 r0 32767; JT r0 3; HALT
+	let mut vm = load(&[1, R0, 2, 9, R0, R0, 32767, 7, R0, 3, 0], Strictness::Lenient);
+	vm.start_profile();
+	vm.execute();
+	let profile = vm.stop_profile().unwrap();
+	assert_eq!(profile.total(), 6);
+	assert_eq!(profile.pc_counts(), vec![(3, 2), (7, 2), (0, 1), (10, 1)]);
+	assert_eq!(profile.opcode_counts(), vec![(7, 2), (9, 2), (0, 1), (1, 1)]);
+	assert!(vm.profile().is_none());
+}
+
+#[test]
+fn profile_charges_functions_by_call_frame() {
+	// CALL 5; HALT; data; data; CALL 9; RET; data; data; NOOP; RET
+	let mut vm = load(&[17, 5, 0, 0, 0, 17, 9, 18, 0, 21, 18], Strictness::Lenient);
+	vm.start_profile();
+	vm.execute();
+	assert_eq!(profile_of(&vm, 5), FunctionProfile { entry: 5, calls: 1, inclusive: 4, exclusive: 2 });
+	assert_eq!(profile_of(&vm, 9), FunctionProfile { entry: 9, calls: 1, inclusive: 2, exclusive: 2 });
+	// the CALL at 0 and the HALT are outside any function
+	assert_eq!(vm.profile().unwrap().total(), 6);
+}
+
+#[test]
+fn profile_follows_frames_past_a_guest_pushed_return() {
+	// CALL f; HALT; f: PUSH 6; RET; RET. The first RET jumps to the address
+	// the guest pushed and f only returns at the second.
+	let mut vm = load(&[17, 3, 0, 2, 6, 18, 18], Strictness::Lenient);
+	vm.start_profile();
+	vm.execute();
+	assert_eq!(clean_halt_at(&vm), Some(2));
+	assert_eq!(profile_of(&vm, 3), FunctionProfile { entry: 3, calls: 1, inclusive: 3, exclusive: 3 });
+}
+
+#[test]
+fn profile_counts_recursion_once() {
+	// r0 = 2; CALL f; HALT; f: JF r0 to the RET; r0 -= 1; CALL f; RET
+	let prog = [1, R0, 2, 17, 6, 0, 8, R0, 16, 9, R0, R0, 32767, 17, 6, 18, 18];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.start_profile();
+	vm.execute();
+	assert_eq!(vm.get_register(0), 0);
+	// three calls: JF, ADD, CALL, RET twice and JF, RET once
+	assert_eq!(profile_of(&vm, 6), FunctionProfile { entry: 6, calls: 3, inclusive: 10, exclusive: 10 });
+}
+
+#[test]
+fn profile_ends_calls_replaced_by_hooks() {
+	let mut vm = load(&[17, 4, 0, 0, 1, R0, 9, 18], Strictness::Lenient);
+	vm.register_hook(4, |_| HookAction::Return);
+	vm.start_profile();
+	vm.execute();
+	assert_eq!(profile_of(&vm, 4), FunctionProfile { entry: 4, calls: 1, inclusive: 0, exclusive: 0 });
+	// calls still open are counted up to now
+	let mut vm = load(&[17, 2, 21, 6, 2], Strictness::Lenient);
+	vm.start_profile();
+	vm.execute_for(3);
+	assert_eq!(profile_of(&vm, 2), FunctionProfile { entry: 2, calls: 1, inclusive: 2, exclusive: 2 });
+}
+
+// (call site, entry, return address) of each frame, innermost first
+fn backtrace_of(vm:&SynacorVM) -> Vec<(u16, u16, u16)> {
+	return vm.backtrace().iter().map(|frame| (frame.call_site, frame.entry, frame.return_addr)).collect();
+}
+
+#[test]
+fn backtrace_lists_the_open_calls() {
+	// CALL 3; HALT; CALL 6; RET; NOOP; RET
+	let mut vm = load(&[17, 3, 0, 17, 6, 18, 21, 18], Strictness::Lenient);
+	vm.run_until(|vm| vm.get_mem_ptr() == 6);
+	assert_eq!(backtrace_of(&vm), vec![(3, 6, 5), (0, 3, 2)]);
+	assert_eq!(vm.call_depth(), 2);
+	vm.run_until(|vm| vm.get_mem_ptr() == 5);
+	assert_eq!(backtrace_of(&vm), vec![(0, 3, 2)]);
+	vm.execute();
+	assert_eq!(backtrace_of(&vm), vec![]);
+	assert_eq!(vm.call_graph().edges(), vec![(None, 3, 1), (Some(3), 6, 1)]);
+	assert_eq!(vm.call_graph().max_call_depth(), 2);
+}
+
+#[test]
+fn backtrace_keeps_frames_past_a_guest_pushed_return() {
+	// CALL f; HALT; f: PUSH 6; RET; RET
+	let mut vm = load(&[17, 3, 0, 2, 6, 18, 18], Strictness::Lenient);
+	vm.run_until(|vm| vm.get_mem_ptr() == 6);
+	assert_eq!(backtrace_of(&vm), vec![(0, 3, 2)]);
+	vm.execute();
+	assert_eq!(clean_halt_at(&vm), Some(2));
+	assert_eq!(vm.call_depth(), 0);
+}
+
+#[test]
+fn call_graph_counts_calls_and_recursion() {
+	// r0 = 2; CALL f; HALT; f: JF r0 to the RET; r0 -= 1; CALL f; RET
+	let prog = [1, R0, 2, 17, 6, 0, 8, R0, 16, 9, R0, R0, 32767, 17, 6, 18, 18];
+	let mut vm = load(&prog, Strictness::Lenient);
+	vm.run_until(|vm| vm.call_depth() == 3);
+	assert_eq!(backtrace_of(&vm), vec![(13, 6, 15), (13, 6, 15), (3, 6, 5)]);
+	vm.execute();
+	let graph = vm.call_graph();
+	assert_eq!(graph.edges(), vec![(Some(6), 6, 2), (None, 6, 1)]);
+	assert_eq!(graph.calls(6), 3);
+	assert_eq!(graph.max_recursion(6), 3);
+	assert_eq!(graph.max_recursion_depths(), vec![(6, 3)]);
+	let dot = graph.to_dot();
+	assert!(dot.starts_with("digraph callgraph {\n"));
+	assert!(dot.contains("\tfn_6 [label=\"fn_6\\ncalls 3\\nmax depth 3\"];\n"));
+	assert!(dot.contains("\troot -> fn_6 [label=\"1\"];\n"));
+	assert!(dot.contains("\tfn_6 -> fn_6 [label=\"2\"];\n"));
+	vm.reset_call_graph();
+	assert_eq!(vm.call_graph().edges(), vec![]);
+	assert_eq!(vm.call_graph().max_recursion(6), 0);
+}
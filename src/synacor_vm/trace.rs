@@ -0,0 +1,209 @@
+// Execution traces, one record per executed instruction.
+//
+// Text format, one line per record:
+//	<seq> #<pc> <MNEMONIC> <operands> [<operand values>] r<n>:<old>-><new> [<addr>]:<old>-><new>
+//
+// Binary format, all values little-endian:
+//	header:	"SYNT", u16 version
+//	record:	u64 seq, u16 pc, u16 opcode,
+//			u8 operand count, then per operand u16 raw and u16 resolved value,
+//			u8 register change count, then per change u8 register, u16 old, u16 new,
+//			u8 memory write count, then per write u16 addr, u16 old, u16 new
+//
+// seq counts every instruction executed since tracing started, including ones
+// that were filtered out, so gaps show where filtered code ran. An IN that
+// waits for input does not complete and takes its number when it runs again.
+use super::{OPCODES, LITERAL_MAX, REG_ADDR_MAX, REG_N};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+const TRACE_MAGIC: &[u8; 4] = b"SYNT";
+pub const TRACE_VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+	Text,
+	Binary,
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceConfig {
+	pub format: TraceFormat,
+	// only record instructions whose address is in this inclusive range
+	pub range: Option<(u16, u16)>,
+	// number of executed instructions to let pass before recording
+	pub skip: u64,
+	// stop recording after this many records
+	pub limit: Option<u64>,
+}
+
+impl Default for TraceConfig {
+	fn default() -> TraceConfig {
+		TraceConfig { format: TraceFormat::Text, range: None, skip: 0, limit: None }
+	}
+}
+
+// state captured before an instruction executes
+pub(super) struct TracePre {
+	seq: u64,
+	pc: u16,
+	opcode: u16,
+	args: Vec<(u16, u16)>,
+	reg: [u16; REG_N],
+}
+
+pub(super) struct Tracer {
+	out: Box<dyn Write + Send>,
+	config: TraceConfig,
+	seq: u64,
+	records: u64,
+	writes: Vec<(u16, u16, u16)>,
+	// the current instruction is being recorded
+	active: bool,
+	error: Option<io::Error>,
+}
+
+fn operand_text(raw:u16) -> String {
+	if raw > LITERAL_MAX && raw <= REG_ADDR_MAX {
+		return format!("reg{}", raw - LITERAL_MAX - 1);
+	}
+	return format!("{}", raw);
+}
+
+impl Tracer {
+	pub(super) fn new(mut out:Box<dyn Write + Send>, config:TraceConfig) -> io::Result<Tracer> {
+		if config.format == TraceFormat::Binary {
+			out.write_all(TRACE_MAGIC)?;
+			out.write_all(&TRACE_VERSION.to_le_bytes())?;
+		}
+		return Ok(Tracer { out, config, seq: 0, records: 0, writes: Vec::new(), active: false, error: None });
+	}
+	// decide whether the instruction at pc is recorded. The sequence number is
+	// used up by end_instruction() once the instruction completes.
+	pub(super) fn wants(&mut self, pc:u16) -> Option<u64> {
+		let seq = self.seq;
+		self.active = self.filter(seq, pc);
+		if !self.active {
+			return None;
+		}
+		return Some(seq);
+	}
+	fn filter(&self, seq:u64, pc:u16) -> bool {
+		if self.error.is_some() || seq < self.config.skip {
+			return false;
+		}
+		if let Some(limit) = self.config.limit {
+			if self.records >= limit {
+				return false;
+			}
+		}
+		if let Some((lo, hi)) = self.config.range {
+			if pc < lo || pc > hi {
+				return false;
+			}
+		}
+		return true;
+	}
+	pub(super) fn pre(seq:u64, pc:u16, opcode:u16, args:Vec<(u16, u16)>, reg:[u16; REG_N]) -> TracePre {
+		return TracePre { seq, pc, opcode, args, reg };
+	}
+	pub(super) fn note_write(&mut self, addr:u16, old:u16, new:u16) {
+		if self.active {
+			self.writes.push((addr, old, new));
+		}
+	}
+	// called after every instruction, with the state captured before it if it
+	// is being recorded. An instruction that did not complete, e.g. IN waiting
+	// for input, will run again and is neither recorded nor counted.
+	pub(super) fn end_instruction(&mut self, pre:Option<TracePre>, reg:&[u16; REG_N], completed:bool) {
+		if !completed {
+			self.active = false;
+			self.writes.clear();
+			return;
+		}
+		self.seq += 1;
+		if let Some(pre) = pre {
+			self.record(pre, reg);
+		}
+	}
+	fn record(&mut self, pre:TracePre, reg:&[u16; REG_N]) {
+		self.active = false;
+		let mut reg_changes:Vec<(usize, u16, u16)> = Vec::new();
+		for (r, (old, new)) in pre.reg.iter().zip(reg.iter()).enumerate() {
+			if old != new {
+				reg_changes.push((r, *old, *new));
+			}
+		}
+		let writes = std::mem::take(&mut self.writes);
+		let result = match self.config.format {
+			TraceFormat::Text => self.write_text(&pre, &reg_changes, &writes),
+			TraceFormat::Binary => self.write_binary(&pre, &reg_changes, &writes),
+		};
+		match result {
+			Ok(_) => self.records += 1,
+			Err(err) => self.error = Some(err),
+		}
+	}
+	fn write_text(&mut self, pre:&TracePre, reg_changes:&[(usize, u16, u16)], writes:&[(u16, u16, u16)]) -> io::Result<()> {
+		let mnemonic = match OPCODES.get(pre.opcode as usize) {
+			Some((name, _)) => name.to_string(),
+			None => format!("DATA? {}", pre.opcode),
+		};
+		let mut line = format!("{} #{} {}", pre.seq, pre.pc, mnemonic);
+		for (raw, _) in pre.args.iter() {
+			line.push(' ');
+			line.push_str(&operand_text(*raw));
+		}
+		if !pre.args.is_empty() {
+			let vals:Vec<String> = pre.args.iter().map(|(_, val)| format!("{}", val)).collect();
+			line.push_str(&format!(" [{}]", vals.join(", ")));
+		}
+		for (r, old, new) in reg_changes {
+			line.push_str(&format!(" r{}:{}->{}", r, old, new));
+		}
+		for (addr, old, new) in writes {
+			line.push_str(&format!(" [{}]:{}->{}", addr, old, new));
+		}
+		return writeln!(self.out, "{}", line);
+	}
+	fn write_binary(&mut self, pre:&TracePre, reg_changes:&[(usize, u16, u16)], writes:&[(u16, u16, u16)]) -> io::Result<()> {
+		let mut rec:Vec<u8> = Vec::new();
+		rec.extend_from_slice(&pre.seq.to_le_bytes());
+		rec.extend_from_slice(&pre.pc.to_le_bytes());
+		rec.extend_from_slice(&pre.opcode.to_le_bytes());
+		rec.push(pre.args.len() as u8);
+		for (raw, val) in pre.args.iter() {
+			rec.extend_from_slice(&raw.to_le_bytes());
+			rec.extend_from_slice(&val.to_le_bytes());
+		}
+		rec.push(reg_changes.len() as u8);
+		for (r, old, new) in reg_changes {
+			rec.push(*r as u8);
+			rec.extend_from_slice(&old.to_le_bytes());
+			rec.extend_from_slice(&new.to_le_bytes());
+		}
+		// one instruction writes at most one word
+		rec.push(writes.len() as u8);
+		for (addr, old, new) in writes {
+			rec.extend_from_slice(&addr.to_le_bytes());
+			rec.extend_from_slice(&old.to_le_bytes());
+			rec.extend_from_slice(&new.to_le_bytes());
+		}
+		return self.out.write_all(&rec);
+	}
+	// flush the output. Returns the number of records written, or the first
+	// error encountered while writing.
+	pub(super) fn finish(mut self) -> io::Result<u64> {
+		if let Some(err) = self.error.take() {
+			return Err(err);
+		}
+		self.out.flush()?;
+		return Ok(self.records);
+	}
+}
+
+pub(super) fn open_file(path:&str) -> io::Result<Box<dyn Write + Send>> {
+	let file = File::create(path)?;
+	return Ok(Box::new(BufWriter::new(file)));
+}
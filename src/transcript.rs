@@ -0,0 +1,113 @@
+// Player input transcripts. A transcript is a text file with one line of game
+// input per line, prefixed with the time it was entered in milliseconds since
+// the unix epoch and a tab:
+//
+//     1760000000000	take tablet
+//
+// Lines starting with # are comments. Lines without a timestamp are replayed
+// as they are, so a hand written list of commands is a valid transcript too.
+use super::synacor_vm::{Input, SynacorVM};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TranscriptEntry {
+	// None for lines written without a timestamp
+	pub millis: Option<u64>,
+	pub command: String,
+}
+
+fn now_millis() -> u64 {
+	return match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(elapsed) => elapsed.as_millis() as u64,
+		Err(_) => 0,
+	};
+}
+
+pub fn parse(text:&str) -> Vec<TranscriptEntry> {
+	let mut entries = Vec::new();
+	for line in text.lines() {
+		if line.starts_with('#') || line.trim().is_empty() {
+			continue;
+		}
+		let entry = match line.split_once('\t') {
+			Some((millis, command)) if millis.parse::<u64>().is_ok() => TranscriptEntry { millis: millis.parse().ok(), command: command.to_string() },
+			_ => TranscriptEntry { millis: None, command: line.to_string() },
+		};
+		entries.push(entry);
+	}
+	return entries;
+}
+pub fn read(path:&str) -> io::Result<Vec<TranscriptEntry>> {
+	return Ok(parse(&fs::read_to_string(path)?));
+}
+
+// appends timestamped lines to a transcript file. Clones write to the same file.
+#[derive(Clone)]
+pub struct Recorder {
+	writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Recorder {
+	// lines are appended, so recording to the transcript being replayed extends it
+	pub fn create(path:&str) -> io::Result<Recorder> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		return Ok(Recorder { writer: Arc::new(Mutex::new(BufWriter::new(file))) });
+	}
+	pub fn record(&self, command:&str) -> io::Result<()> {
+		let mut writer = self.writer.lock().unwrap();
+		writeln!(writer, "{}\t{}", now_millis(), command.trim_end_matches(['\r', '\n']))?;
+		// flushed per line so the transcript survives the game being killed
+		return writer.flush();
+	}
+}
+
+// an Input that records every line read from another Input
+#[derive(Clone)]
+pub struct RecordingInput {
+	inner: Box<dyn Input>,
+	recorder: Recorder,
+}
+
+impl RecordingInput {
+	pub fn new(inner:Box<dyn Input>, recorder:Recorder) -> RecordingInput {
+		return RecordingInput { inner, recorder };
+	}
+}
+
+impl Input for RecordingInput {
+	fn read_line(&mut self) -> Option<String> {
+		let line = self.inner.read_line()?;
+		if let Err(err) = self.recorder.record(&line) {
+			println!("Unable to record input: {}", err);
+		}
+		return Some(line);
+	}
+	fn box_clone(&self) -> Box<dyn Input> {
+		return Box::new(self.clone());
+	}
+}
+
+// feed each command to a vm waiting for input, printing the command and the
+// game's response. Returns the number of commands replayed, which is short of
+// the transcript if the game stopped asking for input.
+pub fn replay(vm:&mut SynacorVM, entries:&[TranscriptEntry], recorder:Option<&Recorder>) -> usize {
+	for (i, entry) in entries.iter().enumerate() {
+		if !vm.is_awaiting_input() {
+			return i;
+		}
+		println!("> {}", entry.command);
+		if let Some(recorder) = recorder {
+			if let Err(err) = recorder.record(&entry.command) {
+				println!("Unable to record input: {}", err);
+			}
+		}
+		vm.input_line(&entry.command);
+		print!("{}", vm.output_line(true));
+	}
+	return entries.len();
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use super::synacor_vm::{SynacorVM, StopReason};
 
 #[derive(Clone)]
 struct TwistyPassagesLink {
@@ -15,17 +16,17 @@ struct TwistyPassagesNode {
 	path:Vec<String>,
 }
 
-// requires a vm at the ladder position
-fn explore_link(vm: &super::synacor_vm::SynacorVM, path:Vec<String>, nodes:&mut HashMap<u16, TwistyPassagesNode>, parent:u16) -> u16 {
-	let node = parse_node(vm, path.clone());
+// requires a vm at the ladder position. Returns None if the node could not be reached.
+fn explore_link(vm: &super::synacor_vm::SynacorVM, path:Vec<String>, nodes:&mut HashMap<u16, TwistyPassagesNode>, parent:u16) -> Option<u16> {
+	let node = parse_node(vm, path.clone())?;
 	let node_id = node.id;
 	if nodes.contains_key(&node_id) {
 		// shorten the path if possible
 		if path.len() < nodes.get(&node.id).unwrap().path.len() {
 			let update_node = nodes.get_mut(&node.id).unwrap();
 			update_node.path.clear();
-			for i in 0..path.len() {
-				update_node.path.push(path[i].clone());
+			for step in path.iter() {
+				update_node.path.push(step.clone());
 			}
 		}
 	}
@@ -45,50 +46,55 @@ fn explore_link(vm: &super::synacor_vm::SynacorVM, path:Vec<String>, nodes:&mut
 	else {
 		println!("Explore_link: node list does not contain an entry for parent node {}", parent);
 	}
-	return node_id;
+	return Some(node_id);
 }
-// requires a vm at the ladder position
-fn parse_node(vm: &super::synacor_vm::SynacorVM, path:Vec<String>) -> TwistyPassagesNode {
+// requires a vm at the ladder position. Returns None if a command along the
+// path fails to come back for more input within the budget.
+fn parse_node(vm: &super::synacor_vm::SynacorVM, path:Vec<String>) -> Option<TwistyPassagesNode> {
 	// by manual inspection, it looks like the current node id is set at memory address 2733
 	let mut vm_clone = vm.clone();
-	for i in 0..path.len() {
-		vm_clone.input_line_string(format!("go {}\n", path[i]));
+	for step in path.iter() {
+		if vm_clone.input_line_for(&format!("go {}", step), SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+			return None;
+		}
 	}
 	// clear output buffer and look
 	let _ = vm_clone.output_line(true);
-	vm_clone.input_line("look");
+	if vm_clone.input_line_for("look", SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+		return None;
+	}
 	let look = vm_clone.output_line(true);
 	let lines:Vec<&str> = look.split(10 as char).collect();
 	let id = vm_clone.get_mem(2733).unwrap();
 	
-	let mut node = TwistyPassagesNode { id: id, links:Vec::new(), items:Vec::new(), path:path.clone()};
+	let mut node = TwistyPassagesNode { id, links:Vec::new(), items:Vec::new(), path:path.clone()};
 	let mut at_items:bool = false;
 	let mut at_exits:bool = false;
-	for i in 0..lines.len() {
-		if lines[i].contains("Things of interest here:") {
+	for line in lines.iter() {
+		if line.contains("Things of interest here:") {
 			at_items = true;
 			continue;
 		}
-		if lines[i].contains("exits:") {
+		if line.contains("exits:") {
 			at_exits = true;
 			continue;
 		}
 		if at_items {
-			if lines[i].len() < 2 {
+			if line.len() < 2 {
 				at_items = false;
 				continue;
 			}
-			node.items.push(lines[i][2..].to_string());
+			node.items.push(line[2..].to_string());
 		}
 		if at_exits {
-			if lines[i].len() < 2 {
+			if line.len() < 2 {
 				at_exits = false;
 				continue;
 			}
-			node.links.push(TwistyPassagesLink { name:lines[i][2..].to_string(), id:0, explored:false });
+			node.links.push(TwistyPassagesLink { name:line[2..].to_string(), id:0, explored:false });
 		}
 	}
-	return node;
+	return Some(node);
 }
 // requires a vm in non-interactive mode that has been placed 
 // at the ladder node in the maze of twisty passages
@@ -100,27 +106,35 @@ pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 	let mut frontier:Vec<u16> = Vec::new();
 	let mut frontier_next:Vec<u16> = Vec::new();
 
-	let start_node = parse_node(&vm_clone, Vec::new());
+	let start_node = match parse_node(&vm_clone, Vec::new()) {
+		Some(node) => node,
+		None => {
+			println!("Unable to look around the starting location.");
+			return false;
+		}
+	};
 	
 	frontier_next.push(start_node.id);
 	nodes.insert(start_node.id, start_node);
 	
-	while frontier_next.len() > 0 {
+	while !frontier_next.is_empty() {
 		frontier.clear();
-		for i in 0..frontier_next.len() {
-			frontier.push(frontier_next[i]);
+		for id in frontier_next.iter() {
+			frontier.push(*id);
 		}
 		frontier_next.clear();
 		
-		for i in 0..frontier.len() {
-			let node = nodes.get_mut(&frontier[i]).unwrap().clone();
+		for id in frontier.iter() {
+			let node = nodes.get_mut(id).unwrap().clone();
 			for j in 0..node.links.len() {
 				if !node.links[j].explored {
 					let mut path = node.path.clone();
 					path.push(node.links[j].name.clone());
-					let link_id = explore_link(&vm_clone, path, &mut nodes, node.id);
-					if !explored.contains(&link_id) {
-						frontier_next.push(link_id);
+					// an unreachable link is left unexplored
+					if let Some(link_id) = explore_link(&vm_clone, path, &mut nodes, node.id) {
+						if !explored.contains(&link_id) {
+							frontier_next.push(link_id);
+						}
 					}
 				}					
 			}
@@ -132,11 +146,14 @@ pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 	println!("Explored {} locations.", explored.len());
 	let mut solution:bool = false;
 	for (_, node) in nodes.iter() {
-		if node.items.len() > 0 {
+		if !node.items.is_empty() {
 			println!("An important location has been discovered!");
 			solution = true;
 			for i in 0..node.path.len() {
-				vm.input_line_string(format!("go {}", node.path[i]));
+				if vm.input_line_for(&format!("go {}", node.path[i]), SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+					println!("The game stopped responding on the way to the {}.", node.items[0]);
+					return false;
+				}
 				if i < node.path.len() - 1 {
 					let _ = vm.output_line(true);
 				}
@@ -144,9 +161,13 @@ pub fn solve(vm:&mut super::synacor_vm::SynacorVM) -> bool {
 				println!("{}", vm.output_line(true));
 			}
 			println!("Taking the {}...", node.items[0]);
-			vm.input_line_string(format!("take {}", node.items[0]));
-			if node.links.len() > 0 {
-				vm.input_line_string(format!("go {}", node.links[0].name));
+			if vm.input_line_for(&format!("take {}", node.items[0]), SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+				println!("The game stopped responding while taking the {}.", node.items[0]);
+				return false;
+			}
+			if !node.links.is_empty() && vm.input_line_for(&format!("go {}", node.links[0].name), SynacorVM::COMMAND_BUDGET) != StopReason::AwaitingInput {
+				println!("The game stopped responding on the way out.");
+				return false;
 			}
 			break;
 		}
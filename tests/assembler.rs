@@ -0,0 +1,62 @@
+// Assembling source text, and round trips through the decompiler.
+#![allow(clippy::needless_return)]
+use synacor_challenge::assembler::{assemble, AsmError};
+use synacor_challenge::interdimensional_physics::decompile;
+
+fn round_trip(program:Vec<u16>) {
+	let source = decompile(&program).join("\n");
+	assert_eq!(assemble(&source), Ok(program));
+}
+
+#[test]
+fn every_opcode_round_trips() {
+	let mut program = Vec::new();
+	for opcode in 0..22u16 {
+		program.extend([opcode, 32768 + opcode % 8, opcode * 3, 32767]);
+	}
+	round_trip(program);
+}
+
+#[test]
+fn decompiler_notation_round_trips() {
+	round_trip(vec![
+		// OUT annotations that look like comments, quotes and brackets
+		19, 35, 19, 59, 19, 34, 19, 39, 19, 40, 19, 41, 19, 32, 19, 10, 19, 9, 19, 200, 19, 300,
+		// stray words, r8 and out of range operands
+		22, 65535, 1, 32776, 40000, 9, 32768, 32777, 1, 2,
+	]);
+}
+
+#[test]
+fn instructions_cut_short_round_trip() {
+	// the decompiler writes an Invalid index marker for each missing operand
+	round_trip(vec![4, 32768]);
+	round_trip(vec![9]);
+	round_trip(vec![21, 9, 1]);
+}
+
+#[test]
+fn labels_data_and_strings() {
+	let source = "
+		start:	SET reg1 'A'		; comment
+				OUT reg1
+				CALL print
+				JMP end
+		print:	RET
+		text:	.data \"hi\\n\" 0x10 text reg0
+		end:	HALT";
+	let words = assemble(source).unwrap();
+	assert_eq!(words, vec![1, 32769, 65, 19, 32769, 17, 9, 6, 16, 18, 104, 105, 10, 16, 10, 32768, 0]);
+}
+
+#[test]
+fn errors_report_the_line() {
+	assert_eq!(assemble("NOOP\nJMP nowhere"), Err(AsmError { line: 2, message: "undefined label nowhere".to_string() }));
+	assert_eq!(assemble("SET reg0"), Err(AsmError { line: 1, message: "expected 2 operands, found 1".to_string() }));
+	assert!(assemble("FROB 1").is_err());
+	assert!(assemble("SET 32768 1").is_err());
+	// characters outside the basic multilingual plane do not fit in a word
+	assert!(assemble("OUT '\u{1F600}'").is_err());
+	assert!(assemble(".data \"\u{1F600}\"").is_err());
+	assert!(assemble("Invalid: HALT").is_err());
+}
@@ -0,0 +1,64 @@
+// Basic blocks and DOT graphs built from the disassembly.
+#![allow(clippy::needless_return)]
+use synacor_challenge::assembler::assemble;
+use synacor_challenge::cfg::{basic_blocks, function_blocks, function_dot, call_graph_dot};
+use synacor_challenge::disassembler::analyze;
+
+const SOURCE: &str = "
+start:	SET reg0 3			; 0
+		CALL count			; 3
+		SET reg1 cb			; 5
+		CALL reg1			; 8
+		HALT				; 10
+count:	JF reg0 done		; 11
+		OUT '\"'			; 14
+		ADD reg0 reg0 32767	; 16
+		CALL count			; 20
+		JMP count			; 22
+done:	RET					; 24
+cb:		RET					; 25
+";
+
+#[test]
+fn splits_blocks_at_jumps_and_targets() {
+	let program = assemble(SOURCE).unwrap();
+	let disassembly = analyze(&program, &[0]);
+	let blocks = basic_blocks(&program, &disassembly);
+	assert_eq!(blocks.keys().cloned().collect::<Vec<u16>>(), vec![0, 11, 14, 24, 25]);
+	// CALL does not end a block
+	assert_eq!(blocks[&0].instructions, vec![0, 3, 5, 8, 10]);
+	assert_eq!(blocks[&0].successors, Vec::<u16>::new());
+	assert_eq!(blocks[&11].successors, vec![24, 14]);
+	assert_eq!(blocks[&14].successors, vec![11]);
+	assert_eq!(function_blocks(&blocks, &disassembly, 11), vec![11, 14, 24]);
+}
+
+#[test]
+fn function_graph_labels_blocks_and_branches() {
+	let program = assemble(SOURCE).unwrap();
+	let disassembly = analyze(&program, &[0]);
+	let blocks = basic_blocks(&program, &disassembly);
+	let dot = function_dot(&program, &disassembly, &blocks, 11);
+	assert!(dot.starts_with("digraph \"fn_11\" {\n"));
+	assert!(dot.contains("\tb11 -> b24 [label=\"false\"];\n"));
+	assert!(dot.contains("\tb11 -> b14 [label=\"true\"];\n"));
+	assert!(dot.contains("\tb14 -> b11;\n"));
+	assert!(dot.contains("b24 [label=\"loc_24:\\lRET"));
+	// quotes in the disassembly are escaped
+	assert!(dot.contains("OUT 34  (\\\")"));
+	// tabs are expanded
+	assert!(!dot.contains("\t#"));
+}
+
+#[test]
+fn call_graph_has_calls_and_callbacks() {
+	let program = assemble(SOURCE).unwrap();
+	let disassembly = analyze(&program, &[0]);
+	let blocks = basic_blocks(&program, &disassembly);
+	let dot = call_graph_dot(&program, &disassembly, &blocks);
+	assert!(dot.contains("\tfn_0 -> fn_11;\n"));
+	assert!(dot.contains("\tfn_11 -> fn_11;\n"));
+	assert!(dot.contains("\tfn_0 -> fn_25 [style=dotted];\n"));
+	assert!(dot.contains("\tfn_0 -> indirect [style=dashed];\n"));
+	assert!(!dot.contains("fn_25 -> "));
+}
@@ -0,0 +1,104 @@
+// Input and output backends for the IN and OUT instructions.
+#![allow(clippy::needless_return)]
+use synacor_challenge::synacor_vm::{SynacorVM, StopReason, Input, Output, FileInput, FileOutput, ChannelInput, BufferOutput};
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
+
+const R0: u16 = 32768;
+
+fn temp_path(name:&str) -> String {
+	let path = std::env::temp_dir().join(format!("synacor-{}-{}.txt", name, std::process::id()));
+	let _ = fs::remove_file(&path);
+	return path.to_string_lossy().into_owned();
+}
+
+#[test]
+fn file_input_reads_lines_until_the_end() {
+	let path = temp_path("file-input");
+	fs::write(&path, "look\ngo north\n").unwrap();
+	let mut input = FileInput::open(&path).unwrap();
+	// clones share the file position
+	let mut clone = input.clone();
+	assert_eq!(input.read_line(), Some("look\n".to_string()));
+	assert_eq!(clone.read_line(), Some("go north\n".to_string()));
+	assert_eq!(input.read_line(), None);
+	fs::remove_file(&path).unwrap();
+	assert!(FileInput::open(&path).is_err());
+}
+
+#[test]
+fn file_input_feeds_the_vm() {
+	let path = temp_path("file-input-vm");
+	fs::write(&path, "hi\nyo\n").unwrap();
+	// IN r0; OUT r0; JMP 0
+	let mut vm = SynacorVM::with_io(Box::new(FileInput::open(&path).unwrap()), Box::new(BufferOutput::new()));
+	vm.load_mem(vec![20, R0, 19, R0, 6, 0]).unwrap();
+	assert_eq!(vm.execute_for(1000), StopReason::AwaitingInput);
+	assert_eq!(vm.output_line(true), "hi\nyo\n");
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn file_output_is_written_on_flush() {
+	let path = temp_path("file-output");
+	let mut output = FileOutput::create(&path).unwrap();
+	// clones write to the same file
+	let mut clone = output.clone();
+	for c in "Hi ".chars() {
+		output.write_char(c);
+	}
+	for c in "\u{e9}\n".chars() {
+		clone.write_char(c);
+	}
+	output.flush().unwrap();
+	assert_eq!(fs::read_to_string(&path).unwrap(), "Hi \u{e9}\n");
+	fs::remove_file(&path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn file_output_reports_a_failed_write_on_flush() {
+	// every write to /dev/full fails. Enough characters to fill the write
+	// buffer fail inside write_char, which keeps the error for flush.
+	let mut output = FileOutput::create("/dev/full").unwrap();
+	for _ in 0..100000 {
+		output.write_char('a');
+	}
+	assert!(output.flush().is_err());
+}
+
+#[test]
+fn blocking_channel_input_waits_for_another_thread() {
+	let (tx, rx) = mpsc::channel();
+	let mut input = ChannelInput::new(rx);
+	let sender = thread::spawn(move || {
+		thread::sleep(std::time::Duration::from_millis(20));
+		tx.send("look\n".to_string()).unwrap();
+		tx.send("go north\n".to_string()).unwrap();
+	});
+	assert_eq!(input.read_line(), Some("look\n".to_string()));
+	assert_eq!(input.read_line(), Some("go north\n".to_string()));
+	// None once the sender is gone
+	sender.join().unwrap();
+	assert_eq!(input.read_line(), None);
+}
+
+#[test]
+fn blocking_channel_input_feeds_the_vm() {
+	let (tx, rx) = mpsc::channel();
+	let runner = thread::spawn(move || {
+		// IN r0; OUT r0; JMP 0
+		let mut vm = SynacorVM::with_io(Box::new(ChannelInput::new(rx)), Box::new(BufferOutput::new()));
+		vm.load_mem(vec![20, R0, 19, R0, 6, 0]).unwrap();
+		let stop = vm.execute_for(1000);
+		return (stop, vm.output_line(true));
+	});
+	tx.send("hi\n".to_string()).unwrap();
+	tx.send("yo\n".to_string()).unwrap();
+	drop(tx);
+	let (stop, output) = runner.join().unwrap();
+	// the closed channel leaves the vm waiting for input
+	assert_eq!(stop, StopReason::AwaitingInput);
+	assert_eq!(output, "hi\nyo\n");
+}
@@ -0,0 +1,81 @@
+// Separating code from data by following control flow.
+#![allow(clippy::needless_return)]
+use synacor_challenge::assembler::assemble;
+use synacor_challenge::disassembler::{find_code, analyze, disassemble, Function};
+
+const R1: u16 = 32769;
+const R2: u16 = 32770;
+
+fn program() -> Vec<u16> {
+	return vec![
+		17, 11,			// 0: CALL 11
+		1, R1, 15,		// 2: SET r1 15, a callback
+		1, R2, 17,		// 5: SET r2 17, a pointer to data
+		17, R1,			// 8: CALL r1
+		0,				// 10: HALT
+		19, 65,			// 11: OUT 65
+		18,				// 13: RET
+		99,				// 14: data
+		21,				// 15: NOOP
+		18,				// 16: RET
+		200, 300,		// 17: data
+	];
+}
+
+#[test]
+fn finds_code_and_callbacks() {
+	let program = program();
+	let map = find_code(&program, &[0]);
+	assert_eq!(map.instructions(), vec![0, 2, 5, 8, 10, 11, 13, 15, 16]);
+	assert!(map.is_code(12));
+	assert!(!map.is_code(14));
+	assert!(!map.is_code(17));
+}
+
+#[test]
+fn listing_reassembles() {
+	let program = program();
+	let lines = disassemble(&program, &analyze(&program, &[0]));
+	assert!(lines.iter().any(|line| line.starts_with(".data 99")));
+	assert!(lines.iter().any(|line| line.starts_with(".data 200 300")));
+	assert_eq!(assemble(&lines.join("\n")), Ok(program));
+}
+
+#[test]
+fn jumps_over_data() {
+	// JMP 4 skips two words that would decode as OUT 72 in a linear sweep,
+	// then JT r1 0 loops back or falls through to HALT
+	let program = vec![6, 4, 19, 72, 7, R1, 0, 0];
+	let map = find_code(&program, &[0]);
+	assert_eq!(map.instructions(), vec![0, 4, 7]);
+	assert!(!map.is_code(2));
+}
+
+#[test]
+fn finds_functions() {
+	let program = program();
+	let disassembly = analyze(&program, &[0]);
+	assert_eq!(disassembly.functions, vec![
+		Function { entry: 0, end: 10, callers: vec![], references: vec![] },
+		Function { entry: 11, end: 13, callers: vec![0], references: vec![] },
+		Function { entry: 15, end: 16, callers: vec![], references: vec![2] },
+	]);
+	let lines = disassemble(&program, &disassembly);
+	assert!(lines.contains(&"; function 11-13, called from 0".to_string()));
+	assert!(lines.contains(&"fn_11:".to_string()));
+	assert!(lines.iter().any(|line| line.starts_with("CALL fn_11")));
+	assert!(lines.contains(&"; function 15-16, address loaded at 2".to_string()));
+	assert!(lines.iter().any(|line| line.starts_with("SET reg1 fn_15")));
+	assert!(lines.iter().any(|line| line.starts_with("SET reg2 17")));
+}
+
+#[test]
+fn labels_jump_targets() {
+	let program = vec![6, 4, 19, 72, 7, R1, 0, 0];
+	let disassembly = analyze(&program, &[0]);
+	assert_eq!(disassembly.labels.get(&4), Some(&"loc_4".to_string()));
+	let lines = disassemble(&program, &disassembly);
+	assert!(lines.iter().any(|line| line.starts_with("JMP loc_4")));
+	assert!(lines.iter().any(|line| line.starts_with("JT reg1 fn_0")));
+	assert_eq!(assemble(&lines.join("\n")), Ok(program));
+}
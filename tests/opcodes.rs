@@ -0,0 +1,302 @@
+// Conformance tests for each of the 22 opcodes, run against small synthetic
+// programs through the public vm api.
+#![allow(clippy::needless_return)]
+use synacor_challenge::synacor_vm::{SynacorVM, HaltReason, VMError, StopReason, Strictness, BufferInput, BufferOutput};
+
+const HALT: u16 = 0;
+const SET: u16 = 1;
+const PUSH: u16 = 2;
+const POP: u16 = 3;
+const EQ: u16 = 4;
+const GT: u16 = 5;
+const JMP: u16 = 6;
+const JT: u16 = 7;
+const JF: u16 = 8;
+const ADD: u16 = 9;
+const MULT: u16 = 10;
+const MOD: u16 = 11;
+const AND: u16 = 12;
+const OR: u16 = 13;
+const NOT: u16 = 14;
+const RMEM: u16 = 15;
+const WMEM: u16 = 16;
+const CALL: u16 = 17;
+const RET: u16 = 18;
+const OUT: u16 = 19;
+const IN: u16 = 20;
+const NOOP: u16 = 21;
+
+// instructions a test program may run before it is considered stuck
+const BUDGET: u64 = 10000;
+
+fn reg(n:u16) -> u16 {
+	return 32768 + n;
+}
+fn run_with(prog:&[u16], strictness:Strictness) -> SynacorVM {
+	let mut vm = SynacorVM::new(false);
+	vm.set_strictness(strictness);
+	vm.load_mem(prog.to_vec()).unwrap();
+	let stop = vm.execute_for(BUDGET);
+	assert!(stop != StopReason::BudgetExhausted, "program did not stop");
+	return vm;
+}
+fn run(prog:&[u16]) -> SynacorVM {
+	return run_with(prog, Strictness::Lenient);
+}
+fn registers(vm:&SynacorVM) -> Vec<u16> {
+	return (0..8).map(|r| vm.get_register(r)).collect();
+}
+fn assert_clean_halt(vm:&SynacorVM, addr:u16) {
+	assert_eq!(vm.halt_reason(), Some(&HaltReason::CleanHalt { addr, opcode: vm.get_mem(addr).unwrap() }));
+}
+fn assert_fault(vm:&SynacorVM, err:VMError) {
+	assert_eq!(vm.halt_reason(), Some(&HaltReason::Fault(err)));
+}
+
+#[test]
+fn halt() {
+	let vm = run(&[HALT, OUT, 65]);
+	assert_clean_halt(&vm, 0);
+	assert_eq!(vm.get_mem_ptr(), 1);
+	assert_eq!(registers(&vm), vec![0; 8]);
+}
+
+#[test]
+fn set() {
+	let vm = run(&[SET, reg(0), 1234, SET, reg(7), reg(0), HALT]);
+	assert_eq!(registers(&vm), vec![1234, 0, 0, 0, 0, 0, 0, 1234]);
+	assert_clean_halt(&vm, 6);
+}
+
+#[test]
+fn push() {
+	let vm = run(&[SET, reg(2), 9, PUSH, 5, PUSH, reg(2), HALT]);
+	assert_eq!(vm.get_stack(), &[5, 9]);
+}
+
+#[test]
+fn pop() {
+	let vm = run(&[PUSH, 5, PUSH, 6, PUSH, 7, POP, reg(0), POP, reg(1), HALT]);
+	assert_eq!(vm.get_register(0), 7);
+	assert_eq!(vm.get_register(1), 6);
+	assert_eq!(vm.get_stack(), &[5]);
+}
+
+#[test]
+fn eq() {
+	let vm = run(&[SET, reg(3), 42, EQ, reg(0), reg(3), 42, EQ, reg(1), reg(3), 41, HALT]);
+	assert_eq!(vm.get_register(0), 1);
+	assert_eq!(vm.get_register(1), 0);
+}
+
+#[test]
+fn gt() {
+	let vm = run(&[GT, reg(0), 32767, 0, GT, reg(1), 0, 32767, GT, reg(2), 5, 5, HALT]);
+	assert_eq!(&registers(&vm)[..3], &[1, 0, 0]);
+}
+
+#[test]
+fn jmp() {
+	// jump over an OUT to a register-valued target
+	let vm = run(&[SET, reg(0), 7, JMP, reg(0), OUT, 65, HALT]);
+	assert_clean_halt(&vm, 7);
+	assert_eq!(vm.clone().output_line(true), "");
+}
+
+#[test]
+fn jt() {
+	let vm = run(&[JT, 0, 6, OUT, 65, NOOP, JT, 32767, 11, OUT, 66, HALT]);
+	assert_eq!(vm.clone().output_line(true), "A");
+	assert_clean_halt(&vm, 11);
+}
+
+#[test]
+fn jf() {
+	let vm = run(&[JF, 1, 6, OUT, 65, NOOP, JF, 0, 11, OUT, 66, HALT]);
+	assert_eq!(vm.clone().output_line(true), "A");
+	assert_clean_halt(&vm, 11);
+}
+
+#[test]
+fn add() {
+	let vm = run(&[ADD, reg(0), 2, 3, ADD, reg(1), reg(0), reg(0), HALT]);
+	assert_eq!(vm.get_register(0), 5);
+	assert_eq!(vm.get_register(1), 10);
+}
+
+#[test]
+fn add_wraps_at_15_bits() {
+	let vm = run(&[ADD, reg(0), 32767, 1, ADD, reg(1), 32767, 32767, ADD, reg(2), 32758, 15, HALT]);
+	assert_eq!(&registers(&vm)[..3], &[0, 32766, 5]);
+}
+
+#[test]
+fn mult() {
+	let vm = run(&[MULT, reg(0), 12, 11, HALT]);
+	assert_eq!(vm.get_register(0), 132);
+}
+
+#[test]
+fn mult_wraps_at_15_bits() {
+	let vm = run(&[MULT, reg(0), 32767, 32767, MULT, reg(1), 16384, 2, MULT, reg(2), 200, 200, HALT]);
+	// 32767 * 32767 = 1073676289 = 32767 * 32768 + 1
+	assert_eq!(&registers(&vm)[..3], &[1, 0, 7232]);
+}
+
+#[test]
+fn modulo() {
+	let vm = run(&[MOD, reg(0), 32767, 10, MOD, reg(1), 3, 7, HALT]);
+	assert_eq!(vm.get_register(0), 7);
+	assert_eq!(vm.get_register(1), 3);
+}
+
+#[test]
+fn modulo_by_zero() {
+	for strictness in [Strictness::Strict, Strictness::Lenient] {
+		let vm = run_with(&[MOD, reg(0), 5, reg(1), HALT], strictness);
+		assert_fault(&vm, VMError::DivisionByZero { addr: 0, opcode: MOD });
+	}
+}
+
+#[test]
+fn and() {
+	let vm = run(&[AND, reg(0), 0b1100, 0b1010, AND, reg(1), 32767, 12345, HALT]);
+	assert_eq!(vm.get_register(0), 0b1000);
+	assert_eq!(vm.get_register(1), 12345);
+}
+
+#[test]
+fn or() {
+	let vm = run(&[OR, reg(0), 0b1100, 0b1010, OR, reg(1), 16384, 16383, HALT]);
+	assert_eq!(vm.get_register(0), 0b1110);
+	assert_eq!(vm.get_register(1), 32767);
+}
+
+#[test]
+fn not_masks_to_15_bits() {
+	let vm = run(&[NOT, reg(0), 0, NOT, reg(1), 32767, NOT, reg(2), 0b101, HALT]);
+	assert_eq!(&registers(&vm)[..3], &[32767, 0, 32762]);
+}
+
+#[test]
+fn rmem() {
+	let vm = run(&[SET, reg(1), 6, RMEM, reg(0), reg(1), 4321]);
+	assert_eq!(vm.get_register(0), 4321);
+}
+
+#[test]
+fn wmem() {
+	let vm = run(&[WMEM, 1000, 77, SET, reg(0), 1001, WMEM, reg(0), reg(0), HALT]);
+	assert_eq!(vm.get_mem(1000), Some(77));
+	assert_eq!(vm.get_mem(1001), Some(1001));
+}
+
+#[test]
+fn wmem_self_modifying_code() {
+	// overwrite the HALT at 3 with NOOP so execution reaches the OUT
+	let vm = run(&[WMEM, 3, NOOP, HALT, OUT, 65, HALT]);
+	assert_eq!(vm.clone().output_line(true), "A");
+	assert_clean_halt(&vm, 6);
+}
+
+#[test]
+fn call() {
+	let vm = run(&[CALL, 4, HALT, HALT, SET, reg(0), 1, HALT]);
+	assert_eq!(vm.get_stack(), &[2]);
+	assert_eq!(vm.get_register(0), 1);
+	assert_clean_halt(&vm, 7);
+}
+
+#[test]
+fn ret() {
+	let vm = run(&[CALL, 3, HALT, OUT, 65, RET]);
+	assert_eq!(vm.get_stack(), &[] as &[u16]);
+	assert_eq!(vm.clone().output_line(true), "A");
+	assert_clean_halt(&vm, 2);
+}
+
+#[test]
+fn ret_empty_stack_halts() {
+	let vm = run(&[RET]);
+	assert_clean_halt(&vm, 0);
+}
+
+#[test]
+fn out() {
+	let mut vm = run(&[SET, reg(0), 10, OUT, 72, OUT, 105, OUT, reg(0), HALT]);
+	assert_eq!(vm.output_line(false), "Hi\n");
+	// output stays buffered until reset
+	assert_eq!(vm.output_line(true), "Hi\n");
+	assert_eq!(vm.output_line(true), "");
+}
+
+#[test]
+fn in_reads_a_line() {
+	let prog = [IN, reg(0), IN, reg(1), IN, reg(2), HALT];
+	let mut vm = SynacorVM::new(false);
+	vm.load_mem(prog.to_vec()).unwrap();
+	assert_eq!(vm.execute(), StopReason::AwaitingInput);
+	vm.set_input_line("ab");
+	vm.execute();
+	assert_eq!(&registers(&vm)[..3], &['a' as u16, 'b' as u16, '\n' as u16]);
+}
+
+#[test]
+fn in_from_input_backend() {
+	let prog = [IN, reg(0), OUT, reg(0), JMP, 0];
+	let input = BufferInput::from_lines(&["hi\n", "yo\n"]);
+	let mut vm = SynacorVM::with_io(Box::new(input), Box::new(BufferOutput::new()));
+	vm.load_mem(prog.to_vec()).unwrap();
+	assert_eq!(vm.execute_for(BUDGET), StopReason::AwaitingInput);
+	assert_eq!(vm.output_line(true), "hi\nyo\n");
+}
+
+#[test]
+fn noop() {
+	let vm = run(&[NOOP, NOOP, NOOP, HALT]);
+	assert_clean_halt(&vm, 3);
+	assert_eq!(registers(&vm), vec![0; 8]);
+	assert_eq!(vm.get_stack().len(), 0);
+}
+
+#[test]
+fn invalid_register_operand() {
+	for strictness in [Strictness::Strict, Strictness::Lenient] {
+		// 32776 is one past r7
+		let vm = run_with(&[ADD, reg(0), 32776, 1, HALT], strictness);
+		assert_fault(&vm, VMError::InvalidOperand { addr: 0, opcode: ADD, operand: 32776 });
+		let vm = run_with(&[SET, 32776, 1, HALT], strictness);
+		assert_fault(&vm, VMError::InvalidOperand { addr: 0, opcode: SET, operand: 32776 });
+		let vm = run_with(&[PUSH, 65535, HALT], strictness);
+		assert_fault(&vm, VMError::InvalidOperand { addr: 0, opcode: PUSH, operand: 65535 });
+	}
+}
+
+#[test]
+fn literal_destination() {
+	for strictness in [Strictness::Strict, Strictness::Lenient] {
+		let vm = run_with(&[SET, 100, 1, HALT], strictness);
+		assert_fault(&vm, VMError::InvalidOperand { addr: 0, opcode: SET, operand: 100 });
+		assert_eq!(vm.get_mem(100), Some(0));
+		assert_eq!(registers(&vm), vec![0; 8]);
+	}
+}
+
+#[test]
+fn undefined_opcode() {
+	for strictness in [Strictness::Strict, Strictness::Lenient] {
+		let vm = run_with(&[NOOP, 22], strictness);
+		assert_fault(&vm, VMError::UndefinedOpcode { addr: 1, opcode: 22 });
+	}
+}
+
+#[test]
+fn program_counter_leaves_memory() {
+	// run off the end of memory from the last two words
+	let mut vm = SynacorVM::new(false);
+	vm.load_mem(vec![JMP, 32766]).unwrap();
+	vm.set_mem(32766, NOOP);
+	vm.set_mem(32767, NOOP);
+	vm.execute_for(BUDGET);
+	assert_fault(&vm, VMError::InvalidProgramCounter { addr: 32768 });
+}
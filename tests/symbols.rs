@@ -0,0 +1,61 @@
+// User names and comments for addresses in the listing.
+#![allow(clippy::needless_return)]
+use synacor_challenge::assembler::assemble;
+use synacor_challenge::disassembler::{analyze, disassemble};
+use synacor_challenge::symbols::{Symbols, SymbolError};
+
+const R0: u16 = 32768;
+
+fn program() -> Vec<u16> {
+	return vec![
+		17, 7,			// 0: CALL 7
+		15, R0, 11,		// 2: RMEM r0 11
+		0,				// 5: HALT
+		99,				// 6: data
+		19, 65,			// 7: OUT 65
+		18,				// 9: RET
+		50,				// 10: data
+		42,				// 11: data
+	];
+}
+
+#[test]
+fn parses_names_and_comments() {
+	let symbols = Symbols::parse("# header\n7 greet ; prints A\n0x0b answer\n\n10 ; padding\n").unwrap();
+	assert_eq!(symbols.name(7).map(|name| name.as_str()), Some("greet"));
+	assert_eq!(symbols.comment(7).map(|comment| comment.as_str()), Some("prints A"));
+	assert_eq!(symbols.name(11).map(|name| name.as_str()), Some("answer"));
+	assert_eq!(symbols.name(10), None);
+	assert_eq!(symbols.comment(10).map(|comment| comment.as_str()), Some("padding"));
+	assert_eq!(symbols.describe(7), "greet (7)");
+	assert_eq!(symbols.describe(5), "5");
+}
+
+#[test]
+fn rejects_bad_lines() {
+	for (text, line) in [("7 greet\nfoo", 2), ("7 reg3", 1), ("7 Invalid", 1), ("7 a\n8 a", 2), ("7 two words", 1), ("70000 big", 1)] {
+		match Symbols::parse(text) {
+			Err(SymbolError::Parse { line: found, .. }) => assert_eq!(found, line, "{}", text),
+			other => panic!("{} parsed as {:?}", text, other),
+		}
+	}
+}
+
+#[test]
+fn listing_uses_symbols_and_reassembles() {
+	let program = program();
+	let symbols = Symbols::parse("7 greet ; prints A\n11 answer\n10 ; padding\n3 inside\n").unwrap();
+	let mut disassembly = analyze(&program, &[0]);
+	disassembly.apply_symbols(&symbols);
+	let lines = disassemble(&program, &disassembly);
+	assert!(lines.iter().any(|line| line.starts_with("CALL greet")));
+	assert!(lines.iter().any(|line| line.starts_with("RMEM reg0 answer")));
+	assert!(lines.iter().any(|line| line == "greet:"));
+	assert!(lines.iter().any(|line| line == "answer:"));
+	assert!(lines.iter().any(|line| line.starts_with("OUT") && line.ends_with("; prints A")));
+	assert!(lines.iter().any(|line| line.starts_with(".data 50") && line.ends_with("; padding")));
+	// an operand word cannot take a label
+	assert!(!lines.iter().any(|line| line == "inside:"));
+	assert!(!lines.iter().any(|line| line.starts_with("fn_7")));
+	assert_eq!(assemble(&lines.join("\n")).unwrap(), program);
+}
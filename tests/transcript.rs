@@ -0,0 +1,78 @@
+// Reading, recording and replaying player input transcripts.
+#![allow(clippy::needless_return)]
+use synacor_challenge::synacor_vm::{SynacorVM, Input, BufferInput};
+use synacor_challenge::transcript::{parse, read, replay, Recorder, RecordingInput, TranscriptEntry};
+use std::fs;
+
+const R0: u16 = 32768;
+const R1: u16 = 32769;
+
+fn entry(millis:Option<u64>, command:&str) -> TranscriptEntry {
+	return TranscriptEntry { millis, command: command.to_string() };
+}
+// a vm running prog, waiting for its first line of input
+fn waiting(prog:&[u16]) -> SynacorVM {
+	let mut vm = SynacorVM::new(false);
+	vm.load_mem(prog.to_vec()).unwrap();
+	vm.execute();
+	assert!(vm.is_awaiting_input());
+	return vm;
+}
+fn temp_path(name:&str) -> String {
+	let path = std::env::temp_dir().join(format!("synacor-{}-{}.txt", name, std::process::id()));
+	let _ = fs::remove_file(&path);
+	return path.to_string_lossy().into_owned();
+}
+
+#[test]
+fn parse_skips_comments_and_blank_lines() {
+	let text = "# a comment\n1760000000000\ttake tablet\n\n   \nuse tablet\n#1760000000001\tnot a command\n12ab\tgo north\n";
+	assert_eq!(parse(text), vec![
+		entry(Some(1760000000000), "take tablet"),
+		entry(None, "use tablet"),
+		// a tab after something other than a timestamp is part of the command
+		entry(None, "12ab\tgo north"),
+	]);
+	assert_eq!(parse(""), vec![]);
+}
+
+#[test]
+fn replay_feeds_each_command() {
+	// IN r0; OUT r0; JMP 0
+	let mut vm = waiting(&[20, R0, 19, R0, 6, 0]);
+	let entries = parse("look\n1760000000000\tgo west\n");
+	assert_eq!(replay(&mut vm, &entries, None), 2);
+	assert!(vm.is_awaiting_input());
+	// the echoed output was taken by replay, so the last character read is the newline
+	assert_eq!(vm.get_register(0), '\n' as u16);
+	assert_eq!(vm.output_line(true), "");
+}
+
+#[test]
+fn replay_stops_when_the_game_stops_asking() {
+	// read up to the end of one line, then halt:
+	// IN r0; EQ r1 r0 10; JF r1 0; HALT
+	let mut vm = waiting(&[20, R0, 4, R1, R0, 10, 8, R1, 0, 0]);
+	let entries = parse("first\nsecond\nthird\n");
+	assert_eq!(replay(&mut vm, &entries, None), 1);
+	assert!(vm.is_halted());
+}
+
+#[test]
+fn recorded_input_reads_back() {
+	let path = temp_path("recorded");
+	let recorder = Recorder::create(&path).unwrap();
+	let mut input = RecordingInput::new(Box::new(BufferInput::from_lines(&["take tablet\n", "use tablet\r\n"])), recorder.clone());
+	assert_eq!(input.read_line().as_deref(), Some("take tablet\n"));
+	assert_eq!(input.read_line().as_deref(), Some("use tablet\r\n"));
+	assert_eq!(input.read_line(), None);
+	// replaying records the commands fed to the game too
+	let mut vm = waiting(&[20, R0, 19, R0, 6, 0]);
+	replay(&mut vm, &parse("go north\n"), Some(&recorder));
+	let commands:Vec<String> = read(&path).unwrap().into_iter().map(|entry| {
+		assert!(entry.millis.is_some());
+		return entry.command;
+	}).collect();
+	assert_eq!(commands, vec!["take tablet", "use tablet", "go north"]);
+	let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,91 @@
+// Driving a vm on its own thread through VmHandle.
+#![allow(clippy::needless_return)]
+use synacor_challenge::synacor_vm::{SynacorVM, VmHandle, VmEvent, StopReason, HaltReason, ChannelInput, ChannelOutput};
+use std::sync::mpsc;
+
+const R0: u16 = 32768;
+
+fn spawn(prog:&[u16]) -> VmHandle {
+	let mut vm = SynacorVM::new(false);
+	vm.load_mem(prog.to_vec()).unwrap();
+	return VmHandle::spawn(vm);
+}
+// wait for the next event that is not output, collecting output on the way
+fn next_stop(handle:&VmHandle, output:&mut String) -> VmEvent {
+	loop {
+		match handle.wait_event().expect("vm thread stopped") {
+			VmEvent::Output(text) => output.push_str(&text),
+			event => return event,
+		}
+	}
+}
+
+#[test]
+fn echoes_queued_input() {
+	// IN r0, OUT r0, JMP 0
+	let handle = spawn(&[20, R0, 19, R0, 6, 0]);
+	handle.input_line("one");
+	handle.input_line("two");
+	// the game may ask for input before the lines arrive
+	let mut output = String::new();
+	while output != "one\ntwo\n" {
+		assert!(matches!(next_stop(&handle, &mut output), VmEvent::AwaitingInput));
+	}
+	let vm = handle.join().unwrap();
+	assert!(vm.is_awaiting_input());
+}
+
+#[test]
+fn reports_halt_and_registers() {
+	// SET r0 7, OUT 'A', HALT
+	let handle = spawn(&[1, R0, 7, 19, 65, 0]);
+	let mut output = String::new();
+	match next_stop(&handle, &mut output) {
+		VmEvent::Halted(reason) => assert_eq!(reason, HaltReason::CleanHalt { addr: 5, opcode: 0 }),
+		_ => panic!("expected a halt"),
+	}
+	assert_eq!(output, "A");
+	handle.registers();
+	match handle.wait_event() {
+		Some(VmEvent::Registers { pc, registers, .. }) => {
+			assert_eq!(pc, 6);
+			assert_eq!(registers[0], 7);
+		},
+		_ => panic!("expected registers"),
+	}
+}
+
+#[test]
+fn breakpoint_pauses_and_step_continues() {
+	// NOOP, NOOP, HALT with a breakpoint on the second NOOP
+	let mut vm = SynacorVM::new(false);
+	vm.load_mem(vec![21, 21, 0]).unwrap();
+	vm.breakpoints_mut().add_pc(1);
+	let handle = VmHandle::spawn(vm);
+	let mut output = String::new();
+	assert!(matches!(next_stop(&handle, &mut output), VmEvent::Breakpoint(StopReason::Breakpoint { addr: 1 })));
+	handle.step(1);
+	assert!(matches!(next_stop(&handle, &mut output), VmEvent::Stepped { pc: 2 }));
+	handle.snapshot();
+	match handle.wait_event() {
+		Some(VmEvent::Snapshot(snapshot)) => assert_eq!(snapshot.get_mem_ptr(), 2),
+		_ => panic!("expected a snapshot"),
+	}
+}
+
+#[test]
+fn replaces_the_io_of_a_vm_built_with_its_own() {
+	// a vm reading from and writing to channels of its own
+	let (_input_tx, input_rx) = mpsc::channel();
+	let (output_tx, output_rx) = mpsc::channel();
+	let mut vm = SynacorVM::with_io(Box::new(ChannelInput::nonblocking(input_rx)), Box::new(ChannelOutput::new(output_tx)));
+	// OUT 'A', IN r0, OUT r0, HALT
+	vm.load_mem(vec![19, 65, 20, R0, 19, R0, 0]).unwrap();
+	let handle = VmHandle::spawn(vm);
+	let mut output = String::new();
+	assert!(matches!(next_stop(&handle, &mut output), VmEvent::AwaitingInput));
+	handle.input_line("b");
+	assert!(matches!(next_stop(&handle, &mut output), VmEvent::Halted(_)));
+	assert_eq!(output, "Ab");
+	assert!(output_rx.try_recv().is_err());
+}